@@ -0,0 +1,70 @@
+use spirv_builder::{Capability, MetadataPrintout, ModuleResult, SpirvBuilder};
+use std::path::{Path, PathBuf};
+
+#[path = "src/shader_cache.rs"]
+mod shader_cache;
+
+use shader_cache::ShaderCache;
+
+// Compiles the shader once at build time and puts it in OUT_DIR/shaders.spv, which
+// gets embedded in the binary. Goes through the same cache in OUT_DIR the app
+// uses, so unchanged sources never get recompiled.
+// If it can't be compiled the file is left empty and the app compiles it at startup.
+fn main() {
+    let manifest_dir = PathBuf::from(env("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(env("OUT_DIR"));
+    let out = out_dir.join("shaders.spv");
+    let cache = ShaderCache::new(&manifest_dir, &out_dir);
+
+    for dir in &cache.crates {
+        println!("cargo:rerun-if-changed={}", dir.join("src").display());
+        println!(
+            "cargo:rerun-if-changed={}",
+            dir.join("Cargo.toml").display()
+        );
+    }
+
+    let data = match cache.load() {
+        Some(data) => data,
+        None => match compile(&cache.crates[0]) {
+            Ok(data) => {
+                // The embedded shader still works without a cache
+                if let Err(err) = cache.save(&data) {
+                    println!("cargo:warning=Couldn't cache the shader: {err}");
+                }
+                data
+            }
+            Err(err) => {
+                println!("cargo:warning=Couldn't compile the shader, it will be compiled at startup instead: {err}");
+                Vec::new()
+            }
+        },
+    };
+
+    // The app checks this against its sources before using the embedded shader
+    println!("cargo:rustc-env=EMBEDDED_SHADER_KEY={}", cache.key());
+
+    std::fs::write(&out, data)
+        .unwrap_or_else(|err| panic!("Couldn't write the shader to {}: {err}", out.display()));
+}
+
+// Cargo always sets these for build scripts
+fn env(name: &str) -> String {
+    std::env::var(name).unwrap_or_else(|err| panic!("Couldn't read {name}: {err}"))
+}
+
+fn compile(shader_crate: &Path) -> Result<Vec<u8>, String> {
+    let compile_res = SpirvBuilder::new(shader_crate, "spirv-unknown-vulkan1.1")
+        .print_metadata(MetadataPrintout::None)
+        .capability(Capability::Int8)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let path = match compile_res.module {
+        ModuleResult::SingleModule(path) => path,
+        ModuleResult::MultiModule(_) => {
+            return Err("got a module per entry point instead of one".to_string())
+        }
+    };
+    std::fs::read(&path).map_err(|err| format!("can't read {}: {err}", path.display()))
+}
@@ -0,0 +1,207 @@
+use glam::Vec3;
+use shared::Camera;
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub pos: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            pos: camera.pos,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            fov: camera.fov,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.pos = self.pos;
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera.fov = self.fov;
+    }
+
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            pos: self.pos.lerp(other.pos, t),
+            yaw: self.yaw + yaw_delta(self.yaw, other.yaw) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+            fov: self.fov + (other.fov - self.fov) * t,
+        }
+    }
+
+    // "x y z yaw pitch fov"
+    pub fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} {} {}",
+            self.pos.x, self.pos.y, self.pos.z, self.yaw, self.pitch, self.fov
+        )
+    }
+
+    pub fn parse(words: &[&str]) -> Result<Self, String> {
+        let v: Vec<f32> = words
+            .iter()
+            .map(|v| v.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.to_string())?;
+        if v.len() != 6 {
+            return Err("expected x y z yaw pitch fov".to_string());
+        }
+
+        Ok(Self {
+            pos: Vec3::new(v[0], v[1], v[2]),
+            yaw: v[3],
+            pitch: v[4],
+            fov: v[5],
+        })
+    }
+}
+
+// Camera keyframes, saved as one "x y z yaw pitch fov" line per keyframe
+#[derive(Default)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    // Camera at t along the whole path, t goes from 0 to 1 and every keyframe
+    // gets the same amount of time. Catmull-Rom spline through all of them.
+    // It takes two keyframes to make a path, None with fewer.
+    pub fn sample(&self, t: f32) -> Option<Keyframe> {
+        let n = self.keyframes.len();
+        if n < 2 {
+            return None;
+        }
+
+        let segments = n - 1;
+        let x = t.clamp(0.0, 1.0) * segments as f32;
+        let i = (x.floor() as usize).min(segments - 1);
+        let t = x - i as f32;
+
+        // Ends get repeated so the spline goes all the way to them
+        let k = |j: isize| self.keyframes[j.clamp(0, n as isize - 1) as usize];
+        let i = i as isize;
+        let (k0, k1, k2, k3) = (k(i - 1), k(i), k(i + 1), k(i + 2));
+
+        // Each yaw relative to the one before, so it turns the short way round
+        let yaw2 = k1.yaw + yaw_delta(k1.yaw, k2.yaw);
+        let yaw0 = k1.yaw - yaw_delta(k0.yaw, k1.yaw);
+        let yaw3 = yaw2 + yaw_delta(k2.yaw, k3.yaw);
+
+        Some(Keyframe {
+            pos: catmull_rom(k0.pos, k1.pos, k2.pos, k3.pos, t),
+            yaw: catmull_rom(yaw0, k1.yaw, yaw2, yaw3, t),
+            pitch: catmull_rom(k0.pitch, k1.pitch, k2.pitch, k3.pitch, t).clamp(-89.0, 89.0),
+            fov: catmull_rom(k0.fov, k1.fov, k2.fov, k3.fov, t),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = String::from("# x y z yaw pitch fov\n");
+        for k in &self.keyframes {
+            out += &k.to_line();
+            out += "\n";
+        }
+        out
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("can't read {}: {err}", path.display()))?;
+        let camera_path = Self::parse(&text)
+            .map_err(|(line, err)| format!("{}:{line}: {err}", path.display()))?;
+
+        if camera_path.keyframes.is_empty() {
+            return Err(format!("{} has no keyframes", path.display()));
+        }
+        Ok(camera_path)
+    }
+
+    // Errors come with their line number
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut keyframes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            keyframes.push(Keyframe::parse(&words).map_err(|err| (i + 1, err))?);
+        }
+        Ok(Self { keyframes })
+    }
+}
+
+// Turn from one yaw to another the short way, in [-180, 180)
+fn yaw_delta(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+fn catmull_rom<T>(p0: T, p1: T, p2: T, p3: T, t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(yaw: f32) -> Keyframe {
+        Keyframe {
+            pos: Vec3::new(0.5, 1.0, -2.25),
+            yaw,
+            pitch: -10.0,
+            fov: 45.0,
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = CameraPath {
+            keyframes: vec![key(0.0), key(90.5), key(-179.0)],
+        };
+        let loaded = CameraPath::parse(&path.to_text()).unwrap();
+        assert!(loaded.keyframes == path.keyframes);
+    }
+
+    #[test]
+    fn needs_two_keyframes() {
+        let path = CameraPath {
+            keyframes: vec![key(0.0)],
+        };
+        assert!(path.sample(0.5).is_none());
+    }
+
+    #[test]
+    fn turns_the_short_way() {
+        // 170 to -170 is 20 degrees through 180, not 340 back through 0
+        let halfway = key(170.0).lerp(&key(-170.0), 0.5).yaw;
+        assert!((halfway.rem_euclid(360.0) - 180.0).abs() < 0.001);
+
+        let path = CameraPath {
+            keyframes: vec![key(170.0), key(-170.0)],
+        };
+        let yaw = path.sample(0.5).unwrap().yaw;
+        assert!((yaw.rem_euclid(360.0) - 180.0).abs() < 0.001);
+    }
+}
@@ -0,0 +1,52 @@
+use shared::{Aovs, Color, NO_SELECTION};
+
+// Passes that can be saved next to the color with --aovs
+pub const AOV_NAMES: [&str; 9] = [
+    "depth", "position", "normal", "albedo", "emission", "direct", "indirect", "object", "material",
+];
+
+// Comma separated pass names, or all of them
+pub fn parse_aovs(value: &str) -> Result<Vec<&'static str>, String> {
+    if value == "all" {
+        return Ok(AOV_NAMES.to_vec());
+    }
+
+    value
+        .split(',')
+        .map(|name| {
+            AOV_NAMES
+                .iter()
+                .find(|&&aov| aov == name.trim())
+                .copied()
+                .ok_or_else(|| {
+                    format!(
+                        "unknown pass {name}, the passes are {}",
+                        AOV_NAMES.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+// One pass as colors for an image. Vectors go in as they are, so normals have
+// negative parts, and ids are -1 where there's no scene file object. Those only
+// really make sense in the float formats.
+pub fn aov_pixels(name: &str, aovs: &[Aovs]) -> Vec<Color> {
+    let gray = |v: f32| Color::new(v, v, v);
+    let id = |id: u32| gray(if id == NO_SELECTION { -1.0 } else { id as f32 });
+
+    aovs.iter()
+        .map(|aov| match name {
+            "depth" => gray(aov.depth),
+            "position" => Color::new(aov.position.x, aov.position.y, aov.position.z),
+            "normal" => Color::new(aov.normal.x, aov.normal.y, aov.normal.z),
+            "albedo" => aov.albedo,
+            "emission" => aov.emission,
+            "direct" => aov.direct,
+            "indirect" => aov.indirect,
+            "object" => id(aov.object),
+            "material" => id(aov.material),
+            _ => panic!("no pass called {name}"),
+        })
+        .collect()
+}
@@ -0,0 +1,135 @@
+use crate::aov::parse_aovs;
+use crate::image_file::ImageOptions;
+use crate::settings::{INTEGRATORS, VIEW_MODES};
+use std::path::PathBuf;
+
+// Command line options, all of them are optional.
+// Giving --render-path renders that camera path without opening a window.
+pub struct Args {
+    pub scene: PathBuf,
+    pub volume: Option<PathBuf>,
+    pub render_path: Option<PathBuf>,
+    pub frames: u32,
+    pub out: PathBuf,
+    // Extension of the frame images
+    pub format: String,
+    pub image_options: ImageOptions,
+    // Extra passes saved with every frame
+    pub aovs: Vec<&'static str>,
+    // Debug view to render instead of the color
+    pub view_mode: u32,
+    pub integrator: u32,
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub video: Option<PathBuf>,
+    pub fps: u32,
+    pub loops: u32,
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        let mut args = Self {
+            scene: PathBuf::from("scene.txt"),
+            volume: None,
+            render_path: None,
+            frames: 60,
+            out: PathBuf::from("frames"),
+            format: "png".to_string(),
+            image_options: ImageOptions::default(),
+            aovs: Vec::new(),
+            view_mode: shared::VIEW_COLOR,
+            integrator: shared::INTEGRATOR_PATH,
+            width: crate::WIN_WIDTH,
+            height: crate::WIN_HEIGHT,
+            samples: 100,
+            video: None,
+            fps: 30,
+            loops: 0,
+        };
+
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let value = iter
+                .next()
+                .unwrap_or_else(|| fail(&format!("{arg} needs a value")));
+
+            match arg.as_str() {
+                "--scene" => args.scene = PathBuf::from(value),
+                "--volume" => args.volume = Some(PathBuf::from(value)),
+                "--render-path" => args.render_path = Some(PathBuf::from(value)),
+                "--frames" => args.frames = number(&arg, &value),
+                "--out" => args.out = PathBuf::from(value),
+                "--format" => args.format = value.trim_start_matches('.').to_lowercase(),
+                "--png-depth" => {
+                    args.image_options.png_16bit = match value.as_str() {
+                        "8" => false,
+                        "16" => true,
+                        _ => fail(&format!("--png-depth is 8 or 16, got {value}")),
+                    }
+                }
+                "--exr-floats" => {
+                    args.image_options.exr_half = match value.as_str() {
+                        "float" => false,
+                        "half" => true,
+                        _ => fail(&format!("--exr-floats is half or float, got {value}")),
+                    }
+                }
+                "--aovs" => args.aovs = parse_aovs(&value).unwrap_or_else(|err| fail(&err)),
+                "--view" => args.view_mode = named(&arg, &value, &VIEW_MODES),
+                "--integrator" => args.integrator = named(&arg, &value, &INTEGRATORS),
+                "--width" => args.width = positive(&arg, &value),
+                "--height" => args.height = positive(&arg, &value),
+                "--samples" => args.samples = positive(&arg, &value),
+                "--video" => args.video = Some(PathBuf::from(value)),
+                "--fps" => args.fps = number(&arg, &value).max(1),
+                "--loops" => args.loops = number(&arg, &value),
+                _ => fail(&format!("unknown argument {arg}")),
+            }
+        }
+
+        args
+    }
+}
+
+fn number(arg: &str, value: &str) -> u32 {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("{arg} needs a number, got {value}")))
+}
+
+// Sizes and sample counts, 0 of them wouldn't render anything
+fn positive(arg: &str, value: &str) -> u32 {
+    match number(arg, value) {
+        0 => fail(&format!("{arg} has to be more than 0")),
+        n => n,
+    }
+}
+
+// One of a list of named modes
+fn named(arg: &str, value: &str, modes: &[(u32, &str)]) -> u32 {
+    modes
+        .iter()
+        .find(|(_, name)| *name == value)
+        .map(|(mode, _)| *mode)
+        .unwrap_or_else(|| {
+            let names: Vec<&str> = modes.iter().map(|(_, name)| *name).collect();
+            fail(&format!(
+                "{arg} is one of {}, got {value}",
+                names.join(", ")
+            ))
+        })
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{msg}");
+    eprintln!(
+        "usage: liamt-raytracer [--scene scene.txt] [--volume grid.vgrid] [--render-path path.txt] \
+         [--frames n] [--out dir] [--format png|exr|pfm|hdr] [--png-depth 8|16] \
+         [--exr-floats half|float] [--aovs all|depth,normal,...] \
+         [--view color|normals|depth|albedo|bounces|variance|nan] \
+         [--integrator path|direct|ao|whitted] [--width px] [--height px] [--samples n] \
+         [--video out.gif|out.apng|out.mp4] [--fps n] [--loops n, 0 loops forever]"
+    );
+    std::process::exit(1)
+}
@@ -0,0 +1,132 @@
+use crate::animation::Keyframe;
+use std::path::{Path, PathBuf};
+
+// How long flying to a bookmark takes
+const TRANSITION_SECONDS: f32 = 0.6;
+
+pub struct Bookmark {
+    pub name: String,
+    pub view: Keyframe,
+}
+
+// Saved camera views, one "x y z yaw pitch fov name" line each. The name is the
+// rest of the line, so only lines starting with # are comments.
+#[derive(Default)]
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    // Bookmarks go next to the scene file, scene.txt has scene.bookmarks
+    pub fn path_for(scene: &Path) -> PathBuf {
+        scene.with_extension("bookmarks")
+    }
+
+    // No file just means no bookmarks yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text)
+                .map_err(|(line, err)| format!("{}:{line}: {err}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("can't read {}: {err}", path.display())),
+        }
+    }
+
+    // Errors come with their line number
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut list = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Six numbers, then everything left is the name
+            let mut words = Vec::new();
+            let mut rest = line;
+            for _ in 0..6 {
+                rest = rest.trim_start();
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                words.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+            let name = rest.trim();
+            if name.is_empty() {
+                return Err((i + 1, "expected x y z yaw pitch fov name".to_string()));
+            }
+
+            list.push(Bookmark {
+                view: Keyframe::parse(&words).map_err(|err| (i + 1, err))?,
+                name: name.to_string(),
+            });
+        }
+
+        Ok(Self { list })
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = String::from("# x y z yaw pitch fov name\n");
+        for bookmark in &self.list {
+            out += &format!("{} {}\n", bookmark.view.to_line(), bookmark.name);
+        }
+        out
+    }
+}
+
+// Smooth move from one view to another
+pub struct Transition {
+    from: Keyframe,
+    to: Keyframe,
+    t: f32,
+}
+
+impl Transition {
+    pub fn new(from: Keyframe, to: Keyframe) -> Self {
+        Self { from, to, t: 0.0 }
+    }
+
+    // View after dt more seconds, and whether it got there
+    pub fn advance(&mut self, dt: f32) -> (Keyframe, bool) {
+        self.t = (self.t + dt / TRANSITION_SECONDS).min(1.0);
+        // Smoothstep so it eases in and out
+        let t = self.t * self.t * (3.0 - 2.0 * self.t);
+        (self.from.lerp(&self.to, t), self.t >= 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn round_trips() {
+        let view = Keyframe {
+            pos: Vec3::new(1.5, -0.25, 3.0),
+            yaw: -170.0,
+            pitch: 12.5,
+            fov: 60.0,
+        };
+        let bookmarks = Bookmarks {
+            list: vec![Bookmark {
+                name: "shot #2, from  the door".to_string(),
+                view,
+            }],
+        };
+
+        let loaded = Bookmarks::parse(&bookmarks.to_text()).unwrap();
+        assert_eq!(loaded.list.len(), 1);
+        assert_eq!(loaded.list[0].name, "shot #2, from  the door");
+        assert!(loaded.list[0].view == view);
+    }
+
+    #[test]
+    fn needs_a_name() {
+        let err = Bookmarks::parse("# comment\n0 0 0 0 0 60\n").err();
+        assert_eq!(err.map(|(line, _)| line), Some(2));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::animation::CameraPath;
+use crate::aov::aov_pixels;
+use crate::args::Args;
+use crate::image_file::{self, Layer};
+use crate::scene::Scene;
+use crate::settings::Settings;
+use crate::video::{VideoEncoder, VideoOptions};
+use crate::voxel::VoxelGrid;
+use glam::{vec4, Vec4};
+use shared::*;
+use std::path::Path;
+
+// Renders every frame of a camera path on the cpu into numbered images
+pub fn render_sequence(args: &Args, path_file: &Path) {
+    let path = CameraPath::load(path_file).unwrap_or_else(|err| panic!("{err}"));
+    if path.keyframes.len() < 2 {
+        panic!(
+            "{} needs at least two keyframes to make a path",
+            path_file.display()
+        );
+    }
+    let grid = VoxelGrid::from_arg(args.volume.as_deref()).unwrap_or_else(|err| panic!("{err}"));
+    let scene = Scene::load(&args.scene).unwrap_or_else(|err| panic!("{err}"));
+    let objects = scene.gpu_objects();
+    let materials = scene.gpu_materials();
+    let settings = Settings {
+        view_mode: args.view_mode,
+        integrator: args.integrator,
+        ..Default::default()
+    };
+
+    std::fs::create_dir_all(&args.out)
+        .unwrap_or_else(|err| panic!("Couldn't create {}: {err}", args.out.display()));
+
+    let mut video = args.video.as_ref().map(|file| {
+        let options = VideoOptions {
+            width: args.width,
+            height: args.height,
+            frames: args.frames,
+            fps: args.fps,
+            loops: args.loops,
+        };
+        VideoEncoder::new(file, &options).unwrap_or_else(|err| panic!("{err}"))
+    });
+
+    for frame in 0..args.frames {
+        let t = if args.frames > 1 {
+            frame as f32 / (args.frames - 1) as f32
+        } else {
+            0.0
+        };
+        let key = path.sample(t).expect("path was checked for keyframes");
+
+        let camera = Camera::new(
+            args.width as f32,
+            args.height as f32,
+            args.samples,
+            key.fov,
+            key.pos,
+            key.yaw,
+            key.pitch,
+        );
+        let consts = settings.consts(&camera, frame, grid.info, &scene, None);
+        // Passes take a bit longer, so they're only done when asked for
+        let (pixels, passes) = if args.aovs.is_empty() {
+            let pixels = render_image(&consts, &grid.density, &objects, &materials);
+            (pixels, Vec::new())
+        } else {
+            let aovs = render_pixels(&consts, |coord| {
+                pixel_aovs(&consts, coord, &grid.density, &objects, &materials)
+            });
+            let pixels = aovs
+                .iter()
+                .map(|aov| aov.view(consts.view_mode, consts.bounce_limit))
+                .collect();
+            let passes: Vec<_> = args
+                .aovs
+                .iter()
+                .map(|&name| (name, aov_pixels(name, &aovs)))
+                .collect();
+            (pixels, passes)
+        };
+
+        let file = args.out.join(format!("frame_{frame:04}.{}", args.format));
+        let mut layers = vec![Layer {
+            name: "",
+            pixels: &pixels,
+        }];
+        layers.extend(passes.iter().map(|(name, pixels)| Layer { name, pixels }));
+        image_file::save_image(
+            &file,
+            args.width,
+            args.height,
+            &layers,
+            args.image_options,
+            &[],
+        )
+        .unwrap_or_else(|err| panic!("{err}"));
+        if let Some(video) = &mut video {
+            video
+                .add_frame(args.width, args.height, &to_rgb8(&pixels))
+                .unwrap_or_else(|err| panic!("{err}"));
+        }
+        println!("Rendered frame {}/{}", frame + 1, args.frames);
+    }
+
+    if let (Some(video), Some(file)) = (video, &args.video) {
+        video.finish().unwrap_or_else(|err| panic!("{err}"));
+        println!("Wrote {}", file.display());
+    }
+}
+
+// Same thing the shader does but on the cpu
+pub fn render_image(
+    consts: &ShaderConsts,
+    voxels: &[f32],
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> Vec<Color> {
+    render_pixels(consts, |coord| {
+        trace_pixel(consts, coord, voxels, objects, materials)
+    })
+}
+
+// Runs pixel for every pixel center, rows are split up over all the threads
+pub fn render_pixels<T: Send>(consts: &ShaderConsts, pixel: impl Fn(Vec4) -> T + Sync) -> Vec<T> {
+    let width = consts.width as usize;
+    let height = consts.height as usize;
+    let mut pixels: Vec<Option<T>> = (0..width * height).map(|_| None).collect();
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = height.div_ceil(threads) * width;
+
+    let pixel = &pixel;
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in pixels.chunks_mut(chunk_size).enumerate() {
+            scope.spawn(move || {
+                for (i, out) in chunk.iter_mut().enumerate() {
+                    let index = chunk_index * chunk_size + i;
+                    let x = (index % width) as f32 + 0.5;
+                    let y = (index / width) as f32 + 0.5;
+                    let coord = vec4(x, y, 0.0, 0.0);
+                    *out = Some(pixel(coord));
+                }
+            });
+        }
+    });
+
+    pixels.into_iter().map(|pixel| pixel.unwrap()).collect()
+}
+
+// Linear colors to packed 8 bit srgb
+pub fn to_rgb8(pixels: &[Color]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.to_srgb();
+            [color.x, color.y, color.z].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Debug builds panic on integer overflow, which the shader code never had to care about
+    #[test]
+    fn renders_a_tiny_frame() {
+        let scene = Scene::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scene.txt")).unwrap();
+        let grid = VoxelGrid::empty();
+        let camera = Camera::new(
+            4.0,
+            3.0,
+            2,
+            90.0,
+            glam::Vec3::new(0.0, 1.0, 2.0),
+            -90.0,
+            0.0,
+        );
+        let consts = Settings::default().consts(&camera, 7, grid.info, &scene, None);
+
+        let pixels = render_image(
+            &consts,
+            &grid.density,
+            &scene.gpu_objects(),
+            &scene.gpu_materials(),
+        );
+        assert_eq!(pixels.len(), 12);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::animation::Keyframe;
+use crate::scene::Scene;
+use crate::settings::Settings;
+use shared::Material;
+
+// Old edits get dropped after this many
+const MAX_EDITS: usize = 100;
+
+// One part of the app as it was before or after an edit
+#[derive(Clone, PartialEq)]
+pub enum State {
+    // Index into Scene::materials
+    Material(usize, Material),
+    // Adding an object can add a material too, so outliner edits keep the scene
+    Objects(Scene),
+    Camera(Keyframe),
+    // With the sample count
+    Settings(Settings, u32),
+}
+
+impl State {
+    // Whether two states are for the same thing, so edits to it can be merged
+    fn same_target(&self, other: &Self) -> bool {
+        match (self, other) {
+            (State::Material(a, _), State::Material(b, _)) => a == b,
+            (State::Objects(_), State::Objects(_))
+            | (State::Camera(_), State::Camera(_))
+            | (State::Settings(..), State::Settings(..)) => true,
+            _ => false,
+        }
+    }
+}
+
+// One change, undoing it goes back to before and redoing it to after
+pub struct Edit {
+    pub name: String,
+    before: State,
+    after: State,
+}
+
+// Edits get recorded where they happen: the material editor, the outliner, and
+// once things settle down for the camera and settings. Reloading the scene file
+// isn't an edit.
+pub struct History {
+    pub edits: Vec<Edit>,
+    // How many of the edits are done, the ones after it can be redone
+    pub position: usize,
+    // The last edit is still going, like a slider being dragged, so more changes
+    // to the same thing go in it
+    open: bool,
+    // Everything as of the last edit, the before states come from here
+    scene: Scene,
+    camera: Keyframe,
+    settings: (Settings, u32),
+}
+
+impl History {
+    pub fn new(scene: &Scene, camera: Keyframe, settings: Settings, samples: u32) -> Self {
+        Self {
+            edits: Vec::new(),
+            position: 0,
+            open: false,
+            scene: scene.clone(),
+            camera,
+            settings: (settings, samples),
+        }
+    }
+
+    // The scene file got loaded again. The scene edits were for the old one so
+    // they go, the camera and settings edits stay.
+    pub fn reset_scene(&mut self, scene: &Scene) {
+        let done = self.position;
+        let mut position = 0;
+        let mut i = 0;
+        self.edits.retain(|edit| {
+            let keep = matches!(edit.after, State::Camera(_) | State::Settings(..));
+            if keep && i < done {
+                position += 1;
+            }
+            i += 1;
+            keep
+        });
+        self.position = position;
+        self.open = false;
+        self.scene = scene.clone();
+    }
+
+    pub fn material_edited(&mut self, index: usize, material: Material) {
+        let Some(old) = self.scene.materials.get_mut(index) else {
+            return;
+        };
+        let name = format!("Changed material {}", old.name);
+        let before = State::Material(index, old.material);
+        old.material = material;
+        self.record(name, before, State::Material(index, material));
+    }
+
+    pub fn objects_edited(&mut self, scene: &Scene) {
+        let before = State::Objects(std::mem::replace(&mut self.scene, scene.clone()));
+        self.record(
+            "Changed objects".to_string(),
+            before,
+            State::Objects(scene.clone()),
+        );
+    }
+
+    // Call once a frame when nothing is being dragged or held. Picks up camera and
+    // settings changes, and ends the edit that was going on. Moving the camera
+    // around several times in a row only makes one edit.
+    pub fn settle(&mut self, camera: Keyframe, settings: Settings, samples: u32) {
+        if camera != self.camera {
+            let before = State::Camera(std::mem::replace(&mut self.camera, camera));
+            self.open = true;
+            self.record("Moved camera".to_string(), before, State::Camera(camera));
+        }
+
+        if (settings, samples) != self.settings {
+            let before = State::Settings(self.settings.0, self.settings.1);
+            self.settings = (settings, samples);
+            self.record(
+                "Changed settings".to_string(),
+                before,
+                State::Settings(settings, samples),
+            );
+        }
+
+        self.open = false;
+    }
+
+    fn last_is(&self, state: &State) -> bool {
+        self.position == self.edits.len()
+            && self
+                .edits
+                .last()
+                .is_some_and(|edit| edit.after.same_target(state))
+    }
+
+    fn record(&mut self, name: String, before: State, after: State) {
+        if self.open && self.last_is(&after) {
+            if let Some(last) = self.edits.last_mut() {
+                last.after = after;
+            }
+            return;
+        }
+
+        self.edits.truncate(self.position);
+        self.edits.push(Edit {
+            name,
+            before,
+            after,
+        });
+        if self.edits.len() > MAX_EDITS {
+            self.edits.remove(0);
+        }
+        self.position = self.edits.len();
+        self.open = true;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.position < self.edits.len()
+    }
+
+    // Moves to having the first position edits done, gives the states to set in order
+    pub fn go_to(&mut self, position: usize) -> Vec<State> {
+        let mut states = Vec::new();
+        if position > self.edits.len() {
+            return states;
+        }
+
+        while self.position > position {
+            self.position -= 1;
+            states.push(self.edits[self.position].before.clone());
+        }
+        while self.position < position {
+            states.push(self.edits[self.position].after.clone());
+            self.position += 1;
+        }
+
+        for state in &states {
+            self.remember(state);
+        }
+        self.open = false;
+        states
+    }
+
+    pub fn undo(&mut self) -> Vec<State> {
+        match self.position {
+            0 => Vec::new(),
+            p => self.go_to(p - 1),
+        }
+    }
+
+    pub fn redo(&mut self) -> Vec<State> {
+        self.go_to(self.position + 1)
+    }
+
+    fn remember(&mut self, state: &State) {
+        match state {
+            State::Material(i, material) => {
+                if let Some(m) = self.scene.materials.get_mut(*i) {
+                    m.material = *material;
+                }
+            }
+            State::Objects(scene) => self.scene = scene.clone(),
+            State::Camera(camera) => self.camera = *camera,
+            State::Settings(settings, samples) => self.settings = (*settings, *samples),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    fn camera(x: f32) -> Keyframe {
+        Keyframe {
+            pos: Vec3::new(x, 1.0, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
+            fov: 60.0,
+        }
+    }
+
+    #[test]
+    fn reloading_keeps_camera_edits() {
+        let scene = Scene::default();
+        let mut history = History::new(&scene, camera(0.0), Settings::default(), 1);
+        history.settle(camera(1.0), Settings::default(), 1);
+        history.objects_edited(&Scene::default());
+        history.settle(camera(2.0), Settings::default(), 1);
+        history.undo();
+
+        history.reset_scene(&scene);
+        assert_eq!(history.edits.len(), 2);
+        assert_eq!(history.position, 1);
+        assert!(history.undo() == vec![State::Camera(camera(0.0))]);
+        assert!(history.redo() == vec![State::Camera(camera(1.0))]);
+    }
+}
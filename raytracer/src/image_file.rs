@@ -0,0 +1,228 @@
+use crate::headless::to_rgb8;
+use exr::prelude::{
+    f16, AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds,
+    LayerAttributes, WritableImage,
+};
+use image::codecs::hdr::HdrEncoder;
+use shared::Color;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Every format the renders can be saved in, picked from the file extension
+pub const EXTENSIONS: [&str; 4] = ["png", "exr", "pfm", "hdr"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Png,
+    Exr,
+    Pfm,
+    Hdr,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "png" => Ok(Self::Png),
+            "exr" => Ok(Self::Exr),
+            "pfm" => Ok(Self::Pfm),
+            "hdr" => Ok(Self::Hdr),
+            _ => Err(format!(
+                "can't save {}, the extension has to be one of {}",
+                path.display(),
+                EXTENSIONS.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImageOptions {
+    // 16 bits per channel instead of 8
+    pub png_16bit: bool,
+    // Half floats instead of full ones
+    pub exr_half: bool,
+}
+
+// One picture in an image file, exr files get them all as layers. The main
+// picture's name can be empty.
+pub struct Layer<'a> {
+    pub name: &'a str,
+    pub pixels: &'a [Color],
+}
+
+// Saves linear colors, the format comes from the extension. Png gets gamma
+// corrected and clamped, the others keep the colors as they are. Formats without
+// layers get every layer after the first in its own file, see layer_path.
+// Text only goes in pngs.
+pub fn save_image(
+    path: &Path,
+    width: u32,
+    height: u32,
+    layers: &[Layer],
+    options: ImageOptions,
+    text: &[(String, String)],
+) -> Result<(), String> {
+    let format = Format::from_path(path)?;
+    if width == 0 || height == 0 {
+        return Err(format!("can't save {}, it has no pixels", path.display()));
+    }
+    let error = |path: &Path, err: String| format!("couldn't save {}: {err}", path.display());
+
+    if format == Format::Exr {
+        return save_exr(path, width, height, layers, options.exr_half)
+            .map_err(|err| error(path, err.to_string()));
+    }
+
+    for (i, layer) in layers.iter().enumerate() {
+        let path = if i == 0 {
+            path.to_path_buf()
+        } else {
+            layer_path(path, layer.name)
+        };
+        let saved = match format {
+            Format::Png if options.png_16bit => save_png(
+                &path,
+                width,
+                height,
+                png::BitDepth::Sixteen,
+                &to_rgb16(layer.pixels),
+                text,
+            ),
+            Format::Png => save_png(
+                &path,
+                width,
+                height,
+                png::BitDepth::Eight,
+                &to_rgb8(layer.pixels),
+                text,
+            ),
+            Format::Pfm => save_pfm(&path, width, height, layer.pixels).map_err(|e| e.to_string()),
+            _ => save_hdr(&path, width, height, layer.pixels),
+        };
+        saved.map_err(|err| error(&path, err))?;
+    }
+
+    Ok(())
+}
+
+// frame.png with a depth layer goes in frame.depth.png
+pub fn layer_path(path: &Path, layer: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    path.with_file_name(format!("{stem}.{layer}.{extension}"))
+}
+
+// Packed big endian 16 bit srgb, the way png wants it
+fn to_rgb16(pixels: &[Color]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.to_srgb();
+            [color.x, color.y, color.z]
+        })
+        .flat_map(|c| ((c.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes())
+        .collect()
+}
+
+fn save_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    depth: png::BitDepth,
+    rgb: &[u8],
+    text: &[(String, String)],
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(depth);
+    for (key, value) in text {
+        encoder
+            .add_text_chunk(key.clone(), value.clone())
+            .map_err(|err| err.to_string())?;
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgb))
+        .map_err(|err| err.to_string())
+}
+
+// Portable float map, rows go from the bottom up
+fn save_pfm(path: &Path, width: u32, height: u32, pixels: &[Color]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    // Negative scale means little endian
+    write!(file, "PF\n{width} {height}\n-1.0\n")?;
+    for row in pixels.chunks(width as usize).rev() {
+        for pixel in row {
+            for c in [pixel.r(), pixel.g(), pixel.b()] {
+                file.write_all(&c.to_le_bytes())?;
+            }
+        }
+    }
+    file.flush()
+}
+
+// Radiance rgbe
+fn save_hdr(path: &Path, width: u32, height: u32, pixels: &[Color]) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let rgb: Vec<image::Rgb<f32>> = pixels
+        .iter()
+        .map(|c| image::Rgb([c.r(), c.g(), c.b()]))
+        .collect();
+    HdrEncoder::new(BufWriter::new(file))
+        .encode(&rgb, width as usize, height as usize)
+        .map_err(|err| err.to_string())
+}
+
+fn save_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    layers: &[Layer],
+    half: bool,
+) -> exr::error::UnitResult {
+    let size = (width as usize, height as usize);
+
+    let layers: Vec<_> = layers
+        .iter()
+        .map(|layer| {
+            let channel = |name: &str, value: fn(&Color) -> f32| {
+                let values = layer.pixels.iter().map(value);
+                let samples = if half {
+                    FlatSamples::F16(values.map(f16::from_f32).collect())
+                } else {
+                    FlatSamples::F32(values.collect())
+                };
+                AnyChannel::new(name, samples)
+            };
+            let channels = vec![
+                channel("R", Color::r),
+                channel("G", Color::g),
+                channel("B", Color::b),
+            ];
+
+            // The main picture doesn't need a name
+            let attributes = if layer.name.is_empty() {
+                LayerAttributes::default()
+            } else {
+                LayerAttributes::named(layer.name)
+            };
+            exr::prelude::Layer::new(
+                size,
+                attributes,
+                Encoding::FAST_LOSSLESS,
+                AnyChannels::sort(channels.into()),
+            )
+        })
+        .collect();
+
+    let attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+    Image::from_layers(attributes, layers).write().to_file(path)
+}
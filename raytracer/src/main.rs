@@ -1,32 +1,125 @@
+use animation::{CameraPath, Keyframe};
+use args::Args;
+use bookmarks::{Bookmark, Bookmarks, Transition};
 use fps_ticker::Fps;
+use history::{History, State};
+use image_file::ImageOptions;
 use nannou::prelude::*;
-use nannou::winit::event::{ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+use nannou::wgpu::util::DeviceExt;
+use nannou::winit::event::{
+    ElementState, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+};
 use nannou_egui::{self, egui, Egui};
+use outliner::{outliner, SceneChange};
+use render_target::RenderTarget;
+use scene::{Scene, MATERIAL_TYPES};
+use settings::{mode_name, Settings, INTEGRATORS, VIEW_MODES};
+use shader::{ShaderError, ShaderEvent, ShaderWatcher};
 use shared::*;
-use spirv_builder::{Capability, MetadataPrintout, SpirvBuilder};
-use std::borrow::Cow;
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use voxel::VoxelGrid;
+
+mod animation;
+mod aov;
+mod args;
+mod bookmarks;
+mod headless;
+mod history;
+mod image_file;
+mod outliner;
+mod render_target;
+mod scene;
+mod screenshot;
+mod settings;
+mod shader;
+mod shader_cache;
+mod video;
+mod voxel;
 
 const WIN_WIDTH: u32 = 800;
 const WIN_HEIGHT: u32 = 600;
 
+// Where the camera path gets saved and loaded from in the app
+const CAMERA_PATH_FILE: &str = "camera_path.txt";
+
+// How long playing the camera path in the app takes
+const PREVIEW_SECONDS: f32 = 5.0;
+
+// Mouse moving less than this many pixels between press and release is a click
+const CLICK_DISTANCE: f32 = 3.0;
+
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some(shader::BUILD_SHADER_ARG) {
+        match shader::build_shader() {
+            Ok(path) => println!("{}", path.display()),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let args = Args::parse();
+    if let Some(path) = &args.render_path {
+        headless::render_sequence(&args, path);
+        return;
+    }
+
     nannou::app(model).update(update).run();
 }
 
 struct Model {
     pipeline: wgpu::RenderPipeline,
+    // Same shader with the linear color, for screenshots
+    capture_pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader_watcher: ShaderWatcher,
+    shader_compiling: bool,
+    shader_error: Option<ShaderError>,
+    bind_group: wgpu::BindGroup,
+    bind_group_layout: wgpu::BindGroupLayout,
+    voxel_buffer: wgpu::Buffer,
+    object_buffer: wgpu::Buffer,
+    material_buffer: wgpu::Buffer,
+    render_target: RenderTarget,
+    render_scale: f32,
+    screenshot_requested: bool,
+    // Extension of a linear copy of screenshots, if any
+    screenshot_hdr: Option<&'static str>,
+    image_options: ImageOptions,
+    screenshot_status: String,
+    scene: Scene,
+    scene_path: PathBuf,
+    scene_modified: Option<SystemTime>,
+    scene_error: Option<String>,
+    volume_error: Option<String>,
+    scene_status: String,
     gui: Egui,
     fps: Fps,
     hold_pos: Option<Point2>,
+    press_pos: Option<Point2>,
+    selected: Option<usize>,
     camera: Camera,
-    bounce_limit: u32,
+    settings: Settings,
     time: u32,
     mouse_speed: f32,
     move_speed: f32,
-    background: [f32; 3],
+    grid: GridInfo,
+    camera_path: CameraPath,
+    preview: Option<f32>,
+    path_status: String,
     current_pressed_keys: HashSet<VirtualKeyCode>,
+    modifiers: ModifiersState,
+    history: History,
+    bookmarks: Bookmarks,
+    // None when the file couldn't be read, so it doesn't get written over
+    bookmarks_path: Option<PathBuf>,
+    bookmark_name: String,
+    bookmark_status: String,
+    transition: Option<Transition>,
 }
 
 fn model(_app: &App) -> Model {
@@ -44,7 +137,6 @@ fn model(_app: &App) -> Model {
         .title("Locked in raytracer")
         .device_descriptor(device_description)
         .size(WIN_WIDTH, WIN_HEIGHT)
-        .resizable(false)
         .view(view)
         .raw_event(raw_event_func)
         .build()
@@ -54,48 +146,408 @@ fn model(_app: &App) -> Model {
     let gui = Egui::from_window(&window);
     let device = window.device();
 
-    let shader_module = device.create_shader_module(load_shader_desc());
+    // load_shader already printed what went wrong
+    let (spirv, shader_error) = shader::load_shader().unwrap_or_else(|_| {
+        eprintln!("No cached shader with the same layout to fall back to either");
+        std::process::exit(1);
+    });
+    let shader_module = device.create_shader_module(shader::shader_desc(&spirv));
+
+    let args = Args::parse();
+    // A broken volume gets shown as an error and the rest renders without it
+    let (grid, volume_error) = match VoxelGrid::from_arg(args.volume.as_deref()) {
+        Ok(grid) => (grid, None),
+        Err(err) => (VoxelGrid::empty(), Some(err)),
+    };
+
+    // A broken scene file shouldn't stop the app, it shows the error and can be fixed
+    let scene_modified = modified_time(&args.scene);
+    let (scene, scene_error) = match Scene::load(&args.scene) {
+        Ok(scene) => (scene, None),
+        Err(err) => (Scene::default(), Some(err)),
+    };
+
+    let voxel_buffer = create_storage_buffer(device, "voxels", &grid.density);
+    let object_buffer = create_storage_buffer(device, "objects", &scene.gpu_objects());
+    let material_buffer = create_storage_buffer(device, "materials", &scene.gpu_materials());
+
+    let bind_group_layout = wgpu::BindGroupLayoutBuilder::new()
+        .storage_buffer(wgpu::ShaderStages::FRAGMENT, false, true)
+        .storage_buffer(wgpu::ShaderStages::FRAGMENT, false, true)
+        .storage_buffer(wgpu::ShaderStages::FRAGMENT, false, true)
+        .build(device);
+
+    let bind_group = create_bind_group(
+        device,
+        &bind_group_layout,
+        [&voxel_buffer, &object_buffer, &material_buffer],
+    );
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("layout"),
-        bind_group_layouts: &[],
+        bind_group_layouts: &[&bind_group_layout],
         push_constant_ranges: &[wgpu::PushConstantRange {
             stages: wgpu::ShaderStages::all(),
             range: 0..std::mem::size_of::<ShaderConsts>() as u32,
         }],
     });
 
-    let pipeline = wgpu::RenderPipelineBuilder::from_layout(&pipeline_layout, &shader_module)
-        .fragment_shader(&shader_module)
-        .vertex_entry_point("main_vs")
-        .fragment_entry_point("main_fs")
-        .sample_count(window.msaa_samples())
-        .build(device);
+    let pipeline = create_pipeline(device, &pipeline_layout, &shader_module, "main_fs");
+    let capture_pipeline =
+        create_pipeline(device, &pipeline_layout, &shader_module, "main_fs_linear");
+    let render_target = RenderTarget::new(
+        device,
+        render_target::scaled_size(window.inner_size_pixels(), 1.0),
+        window.msaa_samples(),
+    );
+
+    let path = Bookmarks::path_for(&args.scene);
+    let (bookmarks, bookmarks_path, bookmark_status) = match Bookmarks::load(&path) {
+        Ok(bookmarks) => (bookmarks, Some(path), String::new()),
+        Err(err) => (
+            Bookmarks::default(),
+            None,
+            format!("{err}, bookmarks won't be saved"),
+        ),
+    };
+
+    let camera = Camera::new(
+        WIN_WIDTH as f32,
+        WIN_HEIGHT as f32,
+        50,
+        90.0,
+        glam::Vec3::new(0.0, 1.0, 2.0),
+        -90.0,
+        0.0,
+    );
+    let settings = Settings::default();
+    let history = History::new(
+        &scene,
+        Keyframe::from_camera(&camera),
+        settings,
+        camera.samples,
+    );
 
     Model {
         pipeline,
+        capture_pipeline,
+        pipeline_layout,
+        shader_watcher: ShaderWatcher::spawn(),
+        shader_compiling: false,
+        shader_error,
+        bind_group,
+        bind_group_layout,
+        voxel_buffer,
+        object_buffer,
+        material_buffer,
+        render_target,
+        render_scale: 1.0,
+        screenshot_requested: false,
+        screenshot_hdr: None,
+        image_options: ImageOptions::default(),
+        screenshot_status: String::new(),
+        scene,
+        scene_path: args.scene,
+        scene_modified,
+        scene_error,
+        volume_error,
+        scene_status: String::new(),
         gui,
         fps: Fps::default(),
         hold_pos: None,
-        bounce_limit: 5,
+        press_pos: None,
+        selected: None,
+        settings,
         time: 0,
         mouse_speed: 20.0,
         move_speed: 30.0,
-        background: [0.0, 0.0, 0.0],
+        grid: grid.info,
+        camera_path: CameraPath::default(),
+        preview: None,
+        path_status: String::new(),
         current_pressed_keys: HashSet::new(),
-        camera: Camera::new(
-            WIN_WIDTH as f32,
-            WIN_HEIGHT as f32,
-            50,
-            90.0,
-            glam::Vec3::new(0.0, 1.0, 2.0),
-            -90.0,
-            0.0,
-        ),
+        modifiers: ModifiersState::default(),
+        history,
+        bookmarks,
+        bookmarks_path,
+        bookmark_name: String::new(),
+        bookmark_status,
+        transition: None,
+        camera,
+    }
+}
+
+// wgpu can't bind an empty storage buffer, so the scene and the voxel grid always
+// have at least one thing in theirs and the shader gets the real counts
+fn create_storage_buffer<T>(device: &wgpu::Device, label: &str, data: &[T]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::BufferInitDescriptor {
+        label: Some(label),
+        contents: unsafe { slice_as_u8_slice(data) },
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+// Voxels, objects and materials, in the order of the shader bindings
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffers: [&wgpu::Buffer; 3],
+) -> wgpu::BindGroup {
+    let mut builder = wgpu::BindGroupBuilder::new();
+    for buffer in buffers {
+        builder = builder.buffer_bytes(buffer, 0, None);
+    }
+    builder.build(device, layout)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+// Reloads the scene when its file changes. The camera and settings stay as they are,
+// and when the file doesn't parse the old scene keeps going.
+fn reload_scene(app: &App, model: &mut Model) {
+    let modified = modified_time(&model.scene_path);
+    if modified == model.scene_modified {
+        return;
+    }
+    model.scene_modified = modified;
+
+    let loaded = Scene::load(&model.scene_path);
+    match replace_scene(
+        &mut model.scene,
+        &mut model.selected,
+        &mut model.history,
+        loaded,
+    ) {
+        Ok(()) => {
+            model.scene_error = None;
+            upload_scene(app, model);
+        }
+        Err(err) => model.scene_error = Some(err),
+    }
+}
+
+// Puts a reloaded scene in place of the old one, only the scene and what points
+// into it change
+fn replace_scene(
+    scene: &mut Scene,
+    selected: &mut Option<usize>,
+    history: &mut History,
+    loaded: Result<Scene, String>,
+) -> Result<(), String> {
+    let loaded = loaded?;
+    if selected.map_or(false, |i| i >= loaded.objects.len()) {
+        *selected = None;
+    }
+    *scene = loaded;
+    history.reset_scene(scene);
+    Ok(())
+}
+
+// Buffer sizes change with the scene, so they get remade along with the bind group
+fn upload_scene(app: &App, model: &mut Model) {
+    let window = app.main_window();
+    let device = window.device();
+
+    model.object_buffer = create_storage_buffer(device, "objects", &model.scene.gpu_objects());
+    model.material_buffer =
+        create_storage_buffer(device, "materials", &model.scene.gpu_materials());
+    model.bind_group = create_bind_group(
+        device,
+        &model.bind_group_layout,
+        [
+            &model.voxel_buffer,
+            &model.object_buffer,
+            &model.material_buffer,
+        ],
+    );
+}
+
+// Says how saving went for the gui
+// The file's new time gets remembered, otherwise the app would see its own save
+// as a change and reload it
+fn save_scene(model: &mut Model) {
+    let saved = model.scene.save(&model.scene_path);
+    if saved.is_ok() {
+        model.scene_modified = modified_time(&model.scene_path);
+    }
+    model.scene_status = save_status(saved, &model.scene_path);
+}
+
+fn save_status(result: std::io::Result<()>, path: &Path) -> String {
+    match result {
+        Ok(()) => format!("Saved to {}", path.display()),
+        Err(err) => format!("Couldn't save: {err}"),
+    }
+}
+
+// Moving objects around doesn't change how many there are either
+fn upload_objects(app: &App, model: &Model) {
+    let data = model.scene.gpu_objects();
+    app.main_window()
+        .queue()
+        .write_buffer(&model.object_buffer, 0, unsafe { slice_as_u8_slice(&data) });
+}
+
+// Editing materials doesn't change how many there are, so the buffer can be overwritten
+fn upload_materials(app: &App, model: &Model) {
+    let data = model.scene.gpu_materials();
+    app.main_window()
+        .queue()
+        .write_buffer(&model.material_buffer, 0, unsafe {
+            slice_as_u8_slice(&data)
+        });
+}
+
+// Sliders for one material, says if anything changed
+fn material_editor(ui: &mut egui::Ui, material: &mut Material) -> bool {
+    let mut changed = false;
+
+    let mut color = [material.color.r(), material.color.g(), material.color.b()];
+    ui.horizontal(|ui| {
+        ui.label("Color");
+        changed |= ui.color_edit_button_rgb(&mut color).changed();
+    });
+    material.color = Color::new(color[0], color[1], color[2]);
+
+    egui::ComboBox::from_label("Type")
+        .selected_text(mode_name(&MATERIAL_TYPES, material.kind))
+        .show_ui(ui, |ui| {
+            for (kind, name) in MATERIAL_TYPES {
+                changed |= ui
+                    .selectable_value(&mut material.kind, kind, name)
+                    .changed();
+            }
+        });
+
+    let sliders = [
+        (&mut material.shininess, 0.0..=1.0, "Shininess"),
+        (&mut material.roughness, 0.0..=1.0, "Roughness"),
+        (&mut material.metallic, 0.0..=1.0, "Metallic"),
+        (&mut material.emission, 0.0..=10.0, "Emission"),
+    ];
+    for (value, range, text) in sliders {
+        changed |= ui.add(egui::Slider::new(value, range).text(text)).changed();
+    }
+
+    // Only glass and the clear coat on plastic refract, and nothing real has an
+    // ior below 1
+    if material.kind == BSDF_DIELECTRIC || material.kind == BSDF_PLASTIC {
+        if material.ior < 1.0 {
+            material.ior = 1.5;
+            changed = true;
+        }
+        let slider = egui::Slider::new(&mut material.ior, 1.0..=3.0).text("IOR");
+        changed |= ui.add(slider).changed();
+    }
+
+    // Glass tints what goes through it, and where glass overlaps the highest
+    // priority one is what's there
+    if material.kind == BSDF_DIELECTRIC {
+        let mut absorption = [
+            material.absorption.r(),
+            material.absorption.g(),
+            material.absorption.b(),
+        ];
+        ui.horizontal(|ui| {
+            ui.label("Absorption");
+            changed |= ui.color_edit_button_rgb(&mut absorption).changed();
+        });
+        material.absorption = Color::new(absorption[0], absorption[1], absorption[2]);
+
+        let slider = egui::Slider::new(&mut material.absorption_distance, 0.0..=10.0)
+            .text("Absorption distance");
+        changed |= ui.add(slider).changed();
+        let slider = egui::Slider::new(&mut material.priority, 0..=10).text("Priority");
+        changed |= ui.add(slider).changed();
+    }
+
+    // In scene units, so it doesn't fit a color picker
+    if material.kind == BSDF_SUBSURFACE {
+        let mut mfp = [
+            material.mean_free_path.r(),
+            material.mean_free_path.g(),
+            material.mean_free_path.b(),
+        ];
+        ui.horizontal(|ui| {
+            ui.label("Mean free path");
+            for value in &mut mfp {
+                let drag = egui::DragValue::new(value)
+                    .speed(0.01)
+                    .clamp_range(0.0..=10.0);
+                changed |= ui.add(drag).changed();
+            }
+        });
+        material.mean_free_path = Color::new(mfp[0], mfp[1], mfp[2]);
+    }
+
+    changed
+}
+
+// Draws into the render target, which has no multisampling
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader_module: &wgpu::ShaderModule,
+    fragment: &str,
+) -> wgpu::RenderPipeline {
+    wgpu::RenderPipelineBuilder::from_layout(layout, shader_module)
+        .fragment_shader(shader_module)
+        .vertex_entry_point("main_vs")
+        .fragment_entry_point(fragment)
+        .sample_count(1)
+        .build(device)
+}
+
+// Remakes the render target when the window size or render scale changed, the
+// camera renders at whatever size it ends up
+fn resize_render_target(app: &App, model: &mut Model) {
+    let window = app.main_window();
+    let size = render_target::scaled_size(window.inner_size_pixels(), model.render_scale);
+    if size != model.render_target.size() {
+        model.render_target = RenderTarget::new(window.device(), size, window.msaa_samples());
+    }
+    model.camera.width = size[0] as f32;
+    model.camera.height = size[1] as f32;
+}
+
+// Swaps in the new pipeline when the watcher has a new shader, the old one
+// keeps running while it compiles or when it fails
+fn reload_shader(app: &App, model: &mut Model) {
+    while let Some(event) = model.shader_watcher.poll() {
+        match event {
+            ShaderEvent::Compiling => model.shader_compiling = true,
+            ShaderEvent::Compiled(spirv) => {
+                let window = app.main_window();
+                let device = window.device();
+                let shader_module = device.create_shader_module(shader::shader_desc(&spirv));
+                let layout = &model.pipeline_layout;
+                model.pipeline = create_pipeline(device, layout, &shader_module, "main_fs");
+                model.capture_pipeline =
+                    create_pipeline(device, layout, &shader_module, "main_fs_linear");
+                model.shader_compiling = false;
+                model.shader_error = None;
+            }
+            ShaderEvent::Failed(err) => {
+                model.shader_compiling = false;
+                model.shader_error = Some(err);
+            }
+        }
     }
 }
 
 fn update(_app: &App, model: &mut Model, _update: Update) {
+    reload_shader(_app, model);
+    reload_scene(_app, model);
+    if model.screenshot_requested {
+        model.screenshot_requested = false;
+        model.screenshot_status = take_screenshot(_app, model);
+    }
+    resize_render_target(_app, model);
+
     let egui = &mut model.gui;
 
     egui.set_elapsed_time(_update.since_start);
@@ -104,6 +556,8 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
     egui::Window::new("Settings").show(&ctx, |ui| {
         ui.label("Move with WASD, space, shift");
         ui.label("Drag mouse to look around");
+        ui.label("Click something to select it");
+        ui.label("F11 for fullscreen, F12 for a screenshot");
 
         ui.add_space(15.0);
         ui.label(format!("FPS: {:.2}", model.fps.avg()));
@@ -111,11 +565,41 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         ui.label(format!("FPS max: {:.2}", model.fps.max()));
         ui.add_space(15.0);
 
+        egui::ComboBox::from_label("View")
+            .selected_text(mode_name(&VIEW_MODES, model.settings.view_mode))
+            .show_ui(ui, |ui| {
+                for (mode, name) in VIEW_MODES {
+                    ui.selectable_value(&mut model.settings.view_mode, mode, name);
+                }
+            });
+
+        egui::ComboBox::from_label("Integrator")
+            .selected_text(mode_name(&INTEGRATORS, model.settings.integrator))
+            .show_ui(ui, |ui| {
+                for (integrator, name) in INTEGRATORS {
+                    ui.selectable_value(&mut model.settings.integrator, integrator, name);
+                }
+            });
+        if model.settings.integrator == INTEGRATOR_AO {
+            ui.label("AO radius");
+            ui.add(egui::Slider::new(&mut model.settings.ao_radius, 0.01..=5.0).logarithmic(true));
+        }
+
         ui.label("Samples");
         ui.add(egui::Slider::new(&mut model.camera.samples, 1..=1000));
 
         ui.label("Bounce limit");
-        ui.add(egui::Slider::new(&mut model.bounce_limit, 1..=20));
+        ui.add(egui::Slider::new(&mut model.settings.bounce_limit, 1..=20));
+
+        let [width, height] = model.render_target.size();
+        ui.label(format!("Render scale ({width}x{height})"));
+        ui.add(
+            egui::Slider::new(
+                &mut model.render_scale,
+                render_target::MIN_SCALE..=render_target::MAX_SCALE,
+            )
+            .custom_formatter(|scale, _| format!("{:.0}%", scale * 100.0)),
+        );
 
         ui.label("FOV");
         ui.add(egui::Slider::new(&mut model.camera.fov, 1.0..=150.0));
@@ -127,8 +611,310 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         ui.add(egui::Slider::new(&mut model.mouse_speed, 1.0..=100.0));
 
         ui.label("Background color");
-        ui.color_edit_button_rgb(&mut model.background);
+        ui.color_edit_button_rgb(&mut model.settings.background);
+
+        ui.checkbox(&mut model.settings.spectral, "Spectral rendering");
+
+        ui.label("Ray march steps");
+        ui.add(egui::Slider::new(&mut model.settings.march_steps, 8..=1000));
+
+        ui.label("Ray march epsilon");
+        ui.add(
+            egui::Slider::new(&mut model.settings.march_epsilon, 0.00001..=0.1).logarithmic(true),
+        );
+
+        ui.add_space(15.0);
+        ui.label("Fog scattering");
+        ui.add(egui::Slider::new(
+            &mut model.settings.fog.medium.scattering,
+            0.0..=2.0,
+        ));
+
+        ui.label("Fog absorption");
+        ui.add(egui::Slider::new(
+            &mut model.settings.fog.medium.absorption,
+            0.0..=2.0,
+        ));
+
+        ui.label("Fog anisotropy");
+        ui.add(egui::Slider::new(
+            &mut model.settings.fog.medium.g,
+            -0.95..=0.95,
+        ));
+
+        ui.label("Fog height");
+        ui.add(egui::Slider::new(&mut model.settings.fog.height, 0.0..=5.0));
+
+        ui.label("Fog color");
+        let mut fog_color = [
+            model.settings.fog.medium.color.r(),
+            model.settings.fog.medium.color.g(),
+            model.settings.fog.medium.color.b(),
+        ];
+        ui.color_edit_button_rgb(&mut fog_color);
+        model.settings.fog.medium.color = Color::new(fog_color[0], fog_color[1], fog_color[2]);
+
+        if model.grid.dims.0 > 0 {
+            ui.label("Volume density");
+            ui.add(egui::Slider::new(
+                &mut model.settings.grid_density,
+                0.0..=10.0,
+            ));
+        }
+
+        ui.add_space(15.0);
+        ui.label(format!(
+            "Camera path: {} keyframes",
+            model.camera_path.keyframes.len()
+        ));
+        ui.horizontal(|ui| {
+            if ui.button("Add keyframe (K)").clicked() {
+                let keyframe = Keyframe::from_camera(&model.camera);
+                model.camera_path.keyframes.push(keyframe);
+            }
+            if ui.button("Remove last").clicked() {
+                model.camera_path.keyframes.pop();
+            }
+            if ui.button("Clear").clicked() {
+                model.camera_path.keyframes.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Play").clicked() && model.camera_path.keyframes.len() >= 2 {
+                model.preview = Some(0.0);
+            }
+            if ui.button("Save").clicked() {
+                model.path_status = match model.camera_path.save(Path::new(CAMERA_PATH_FILE)) {
+                    Ok(()) => format!("Saved to {CAMERA_PATH_FILE}"),
+                    Err(err) => format!("Couldn't save: {err}"),
+                };
+            }
+            if ui.button("Load").clicked() {
+                model.path_status = match CameraPath::load(Path::new(CAMERA_PATH_FILE)) {
+                    Ok(path) => {
+                        model.camera_path = path;
+                        format!("Loaded {CAMERA_PATH_FILE}")
+                    }
+                    Err(err) => err,
+                };
+            }
+        });
+        if !model.path_status.is_empty() {
+            ui.label(&model.path_status);
+        }
+
+        ui.add_space(15.0);
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot (F12)").clicked() {
+                model.screenshot_requested = true;
+            }
+            egui::ComboBox::from_label("Also save")
+                .selected_text(model.screenshot_hdr.unwrap_or("nothing"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut model.screenshot_hdr, None, "nothing");
+                    for extension in ["exr", "pfm", "hdr"] {
+                        ui.selectable_value(&mut model.screenshot_hdr, Some(extension), extension);
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut model.image_options.png_16bit, "16 bit png");
+            ui.checkbox(&mut model.image_options.exr_half, "Half float exr");
+        });
+        if !model.screenshot_status.is_empty() {
+            ui.label(&model.screenshot_status);
+        }
+
+        if model.shader_compiling {
+            ui.add_space(15.0);
+            ui.label("Compiling shader...");
+        }
+    });
+
+    let mut materials_changed = false;
+    egui::Window::new("Materials").show(&ctx, |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, m) in model.scene.materials.iter_mut().enumerate() {
+                egui::CollapsingHeader::new(m.name.as_str())
+                    .id_source(i)
+                    .show(ui, |ui| {
+                        if material_editor(ui, &mut m.material) {
+                            model.history.material_edited(i, m.material);
+                            materials_changed = true;
+                        }
+                    });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                save_scene(model);
+            }
+            // Forgetting when it was loaded makes it get loaded again
+            if ui.button("Load").clicked() {
+                model.scene_modified = None;
+                model.scene_status = String::new();
+            }
+        });
+        if !model.scene_status.is_empty() {
+            ui.label(&model.scene_status);
+        }
+    });
+    if materials_changed {
+        upload_materials(_app, model);
+    }
+
+    let mut scene_change = SceneChange::Nothing;
+    egui::Window::new("Scene").show(&ctx, |ui| {
+        scene_change = outliner(ui, &mut model.scene, &mut model.selected);
+
+        ui.separator();
+        if ui.button("Save to scene file").clicked() {
+            save_scene(model);
+        }
     });
+    if scene_change != SceneChange::Nothing {
+        model.history.objects_edited(&model.scene);
+    }
+    match scene_change {
+        SceneChange::Nothing => {}
+        SceneChange::Edited => upload_objects(_app, model),
+        SceneChange::Resized => upload_scene(_app, model),
+    }
+
+    let mut go_to = None;
+    egui::Window::new("Bookmarks").show(&ctx, |ui| {
+        ui.label("Number keys jump to the first nine");
+        let mut remove = None;
+        for (i, bookmark) in model.bookmarks.list.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button(format!("{}: {}", i + 1, bookmark.name)).clicked() {
+                    go_to = Some(i);
+                }
+                if ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut model.bookmark_name);
+            if ui.button("Add").clicked() {
+                let name = match model.bookmark_name.trim() {
+                    "" => format!("View {}", model.bookmarks.list.len() + 1),
+                    name => name.to_string(),
+                };
+                model.bookmarks.list.push(Bookmark {
+                    name,
+                    view: Keyframe::from_camera(&model.camera),
+                });
+                model.bookmark_name.clear();
+                if let Some(path) = &model.bookmarks_path {
+                    model.bookmark_status = save_status(model.bookmarks.save(path), path);
+                }
+            }
+        });
+
+        if let Some(i) = remove {
+            model.bookmarks.list.remove(i);
+            if let Some(path) = &model.bookmarks_path {
+                model.bookmark_status = save_status(model.bookmarks.save(path), path);
+            }
+        }
+        if !model.bookmark_status.is_empty() {
+            ui.label(&model.bookmark_status);
+        }
+    });
+    if let Some(i) = go_to {
+        go_to_bookmark(model, i);
+    }
+
+    let mut jump = None;
+    egui::Window::new("History").show(&ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(model.history.can_undo(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                jump = Some(model.history.position - 1);
+            }
+            if ui
+                .add_enabled(model.history.can_redo(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                jump = Some(model.history.position + 1);
+            }
+        });
+        ui.label("Ctrl+Z to undo, Ctrl+Shift+Z to redo");
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .show(ui, |ui| {
+                if ui
+                    .selectable_label(model.history.position == 0, "Start")
+                    .clicked()
+                {
+                    jump = Some(0);
+                }
+                for (i, edit) in model.history.edits.iter().enumerate() {
+                    // Undone edits are greyed out
+                    let text = if i < model.history.position {
+                        egui::RichText::new(&edit.name)
+                    } else {
+                        egui::RichText::new(&edit.name).weak()
+                    };
+                    if ui
+                        .selectable_label(model.history.position == i + 1, text)
+                        .clicked()
+                    {
+                        jump = Some(i + 1);
+                    }
+                }
+            });
+    });
+    if let Some(position) = jump {
+        let states = model.history.go_to(position);
+        apply_states(_app, model, states);
+    }
+
+    if let Some(err) = &model.scene_error {
+        egui::Window::new("Scene error").show(&ctx, |ui| {
+            ui.label("Still showing the last scene that loaded");
+            ui.monospace(err);
+        });
+    }
+
+    if let Some(err) = &model.volume_error {
+        egui::Window::new("Volume error").show(&ctx, |ui| {
+            ui.label("Rendering without the volume");
+            ui.monospace(err);
+        });
+    }
+
+    if let Some(err) = &model.shader_error {
+        egui::Window::new("Shader error").show(&ctx, |ui| {
+            ui.label("Still running the last shader that worked");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.monospace(err.to_string());
+            });
+        });
+    }
+
+    if let Some(transition) = &mut model.transition {
+        let (view, done) = transition.advance(_update.since_last.as_secs_f32());
+        view.apply(&mut model.camera);
+        if done {
+            model.transition = None;
+        }
+    }
+
+    if let Some(t) = model.preview {
+        if let Some(key) = model.camera_path.sample(t) {
+            key.apply(&mut model.camera);
+        }
+        let t = t + _update.since_last.as_secs_f32() / PREVIEW_SECONDS;
+        model.preview = if t <= 1.0 { Some(t) } else { None };
+    }
 
     if !model.current_pressed_keys.is_empty() {
         let forward_vector = model.camera.direction();
@@ -161,33 +947,121 @@ fn update(_app: &App, model: &mut Model, _update: Update) {
         }
     }
 
+    // Waits until nothing is held down so a whole drag or walk is one edit
+    if model.hold_pos.is_none()
+        && model.current_pressed_keys.is_empty()
+        && model.preview.is_none()
+        && model.transition.is_none()
+    {
+        model.history.settle(
+            Keyframe::from_camera(&model.camera),
+            model.settings,
+            model.camera.samples,
+        );
+    }
+
     model.time += 1;
 }
 
+// Renders a frame like the last one without the gui and the selection tint, and
+// the light on its own for the linear copy. Says how it went for the gui.
+fn take_screenshot(app: &App, model: &Model) -> String {
+    let window = app.main_window();
+    let size = model.render_target.size();
+    let metadata = screenshot::metadata(&model.scene_path, &model.camera, &model.settings);
+    let mut constants =
+        model
+            .settings
+            .consts(&model.camera, model.time, model.grid, &model.scene, None);
+    let capture = |constants: &ShaderConsts| {
+        screenshot::capture(
+            window.device(),
+            window.queue(),
+            &model.capture_pipeline,
+            &model.bind_group,
+            unsafe { any_as_u8_slice(constants) },
+            size,
+        )
+    };
+
+    let saved = capture(&constants).and_then(|pixels| {
+        // The view modes are only for looking at, the float formats get the light
+        let light = match model.screenshot_hdr {
+            Some(_) if constants.view_mode != VIEW_COLOR => {
+                constants.view_mode = VIEW_COLOR;
+                Some(capture(&constants)?)
+            }
+            _ => None,
+        };
+        let hdr_pixels = light.as_deref().unwrap_or(&pixels);
+        let hdr = model
+            .screenshot_hdr
+            .map(|extension| (extension, hdr_pixels));
+        screenshot::save_screenshot(size, &pixels, &metadata, model.image_options, hdr)
+    });
+    match saved {
+        Ok(path) => format!("Saved {}", path.display()),
+        Err(err) => format!("Screenshot failed: {err}"),
+    }
+}
+
+fn go_to_bookmark(model: &mut Model, i: usize) {
+    if let Some(bookmark) = model.bookmarks.list.get(i) {
+        let from = Keyframe::from_camera(&model.camera);
+        model.transition = Some(Transition::new(from, bookmark.view));
+    }
+}
+
+// Undoing or redoing, the states come from the history
+fn apply_states(app: &App, model: &mut Model, states: Vec<State>) {
+    let mut scene_changed = false;
+    for state in states {
+        match state {
+            State::Material(i, material) => {
+                if let Some(m) = model.scene.materials.get_mut(i) {
+                    m.material = material;
+                    scene_changed = true;
+                }
+            }
+            State::Objects(scene) => {
+                model.scene = scene;
+                scene_changed = true;
+            }
+            State::Camera(camera) => camera.apply(&mut model.camera),
+            State::Settings(settings, samples) => {
+                model.settings = settings;
+                model.camera.samples = samples;
+            }
+        }
+    }
+
+    if scene_changed {
+        if model
+            .selected
+            .map_or(false, |i| i >= model.scene.objects.len())
+        {
+            model.selected = None;
+        }
+        upload_scene(app, model);
+    }
+}
+
 fn view(app: &App, model: &Model, frame: Frame) {
     let mut encoder = frame.command_encoder();
 
     let mut render_pass = wgpu::RenderPassBuilder::new()
-        .color_attachment(frame.texture_view(), |color| color)
+        .color_attachment(&model.render_target.view, |color| color)
         .begin(&mut encoder);
     render_pass.set_pipeline(&model.pipeline);
+    render_pass.set_bind_group(0, &model.bind_group, &[]);
 
-    let constants = ShaderConsts {
-        time: model.time,
-        bounce_limit: model.bounce_limit,
-        width: model.camera.width,
-        height: model.camera.height,
-        samples: model.camera.samples,
-        fov: model.camera.fov,
-        pos: (model.camera.pos.x, model.camera.pos.y, model.camera.pos.z),
-        yaw: model.camera.yaw,
-        pitch: model.camera.pitch,
-        background: (
-            model.background[0],
-            model.background[1],
-            model.background[2],
-        ),
-    };
+    let constants = model.settings.consts(
+        &model.camera,
+        model.time,
+        model.grid,
+        &model.scene,
+        model.selected,
+    );
 
     let bytes = unsafe { any_as_u8_slice(&constants) };
     render_pass.set_push_constants(wgpu::ShaderStages::all(), 0, bytes);
@@ -197,6 +1071,7 @@ fn view(app: &App, model: &Model, frame: Frame) {
     drop(render_pass);
     drop(encoder);
 
+    model.render_target.draw_to_frame(&frame);
     model.gui.draw_to_frame(&frame);
     model.fps.tick();
 }
@@ -208,13 +1083,20 @@ fn raw_event_func(app: &App, model: &mut Model, event: &WindowEvent) {
         if *button == MouseButton::Left {
             match state {
                 ElementState::Pressed => {
-                    model.hold_pos = Some(vec2(
-                        app.mouse.position().x + (WIN_WIDTH as f32 / 2.0),
-                        -app.mouse.position().y + (WIN_HEIGHT as f32 / 2.0),
-                    ));
+                    model.hold_pos = Some(mouse_pixel(app));
+                    // Clicks on the gui aren't for picking
+                    if !model.gui.ctx().is_pointer_over_area() {
+                        model.press_pos = model.hold_pos;
+                    }
                 }
                 ElementState::Released => {
                     model.hold_pos = None;
+                    if let Some(start) = model.press_pos.take() {
+                        let pos = mouse_pixel(app);
+                        if start.distance(pos) < CLICK_DISTANCE {
+                            pick(app, model, pos);
+                        }
+                    }
                 }
             }
         }
@@ -233,10 +1115,47 @@ fn raw_event_func(app: &App, model: &mut Model, event: &WindowEvent) {
         }
     }
 
+    if let WindowEvent::ModifiersChanged(modifiers) = event {
+        model.modifiers = *modifiers;
+    }
+
     if let WindowEvent::KeyboardInput { input, .. } = event {
         if let Some(keycode) = input.virtual_keycode {
             match input.state {
                 ElementState::Pressed => {
+                    // Text fields have their own undo
+                    if keycode == VirtualKeyCode::Z
+                        && (model.modifiers.ctrl() || model.modifiers.logo())
+                        && !model.gui.ctx().wants_keyboard_input()
+                    {
+                        let states = if model.modifiers.shift() {
+                            model.history.redo()
+                        } else {
+                            model.history.undo()
+                        };
+                        apply_states(app, model, states);
+                        return;
+                    }
+                    if !model.gui.ctx().wants_keyboard_input() {
+                        if let Some(i) = number_key(keycode) {
+                            go_to_bookmark(model, i);
+                        }
+                    }
+                    if keycode == VirtualKeyCode::F11
+                        && !model.current_pressed_keys.contains(&keycode)
+                    {
+                        let window = app.main_window();
+                        window.set_fullscreen(!window.is_fullscreen());
+                    }
+                    if keycode == VirtualKeyCode::F12 {
+                        model.screenshot_requested = true;
+                    }
+                    if keycode == VirtualKeyCode::K
+                        && !model.current_pressed_keys.contains(&keycode)
+                    {
+                        let keyframe = Keyframe::from_camera(&model.camera);
+                        model.camera_path.keyframes.push(keyframe);
+                    }
                     model.current_pressed_keys.insert(keycode);
                 }
                 ElementState::Released => {
@@ -247,32 +1166,126 @@ fn raw_event_func(app: &App, model: &mut Model, event: &WindowEvent) {
     }
 }
 
+// Index the number keys 1 to 9 stand for
+fn number_key(keycode: VirtualKeyCode) -> Option<usize> {
+    let keys = [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+    ];
+    keys.iter().position(|&key| key == keycode)
+}
+
+// Mouse position in points from the top left of the window
+fn mouse_pixel(app: &App) -> Point2 {
+    let rect = app.main_window().rect();
+    vec2(
+        app.mouse.position().x - rect.left(),
+        rect.top() - app.mouse.position().y,
+    )
+}
+
+// Selects the scene object under the mouse, same ray as the shader shoots through
+// that pixel of the render target
+fn pick(app: &App, model: &mut Model, pos: Point2) {
+    let window_size = app.main_window().rect().wh();
+    let [width, height] = model.render_target.size();
+    let pos = pos / window_size * vec2(width as f32, height as f32);
+
+    let constants =
+        model
+            .settings
+            .consts(&model.camera, model.time, model.grid, &model.scene, None);
+    let object = object_at(
+        &constants,
+        glam::vec4(pos.x, pos.y, 0.0, 0.0),
+        &model.scene.gpu_objects(),
+        &model.scene.gpu_materials(),
+    );
+    model.selected = (object != NO_SELECTION).then_some(object as usize);
+}
+
 // From https://stackoverflow.com/questions/28127165/how-to-convert-struct-to-u8
 unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     std::slice::from_raw_parts((p as *const T) as *const u8, std::mem::size_of::<T>())
 }
 
-fn load_shader_desc() -> wgpu::ShaderModuleDescriptor<'static> {
-    let crate_path = [env!("CARGO_MANIFEST_DIR"), "..", "shaders"]
-        .iter()
-        .copied()
-        .collect::<PathBuf>();
+unsafe fn slice_as_u8_slice<T: Sized>(p: &[T]) -> &[u8] {
+    std::slice::from_raw_parts(p.as_ptr() as *const u8, std::mem::size_of_val(p))
+}
 
-    let compile_res = SpirvBuilder::new(crate_path, "spirv-unknown-vulkan1.1")
-        .print_metadata(MetadataPrintout::None)
-        .capability(Capability::Int8)
-        .build()
-        .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scene::{NamedMaterial, Object, Shape};
 
-    let data = std::fs::read(compile_res.module.unwrap_single()).unwrap();
-    let spirv = wgpu::util::make_spirv(&data);
+    fn one_sphere() -> Scene {
+        Scene {
+            materials: vec![NamedMaterial {
+                name: "white".to_string(),
+                material: Material::default(),
+            }],
+            objects: vec![Object {
+                shape: Shape::Sphere {
+                    center: glam::Vec3::ZERO,
+                    radius: 1.0,
+                },
+                material: 0,
+            }],
+        }
+    }
 
-    let source = match spirv {
-        wgpu::ShaderSource::SpirV(cow) => wgpu::ShaderSource::SpirV(Cow::Owned(cow.into_owned())),
-        _ => panic!("Unexpected shader source"),
-    };
-    wgpu::ShaderModuleDescriptor {
-        label: Some("shader desc"),
-        source,
+    fn camera() -> Keyframe {
+        Keyframe {
+            pos: glam::Vec3::new(1.0, 2.0, 3.0),
+            yaw: 0.5,
+            pitch: 0.1,
+            fov: 60.0,
+        }
+    }
+
+    #[test]
+    fn reload_keeps_the_camera() {
+        let camera = camera();
+        let mut scene = Scene::default();
+        let mut history = History::new(&scene, camera, Settings::default(), 1);
+        let mut selected = None;
+
+        let loaded = Ok(one_sphere());
+        assert!(replace_scene(&mut scene, &mut selected, &mut history, loaded).is_ok());
+        assert!(scene == one_sphere());
+
+        // The history still has the same camera and settings, so nothing looks moved
+        history.settle(camera, Settings::default(), 1);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn parse_error_keeps_the_old_scene() {
+        let mut scene = one_sphere();
+        let mut history = History::new(&scene, camera(), Settings::default(), 1);
+        let mut selected = Some(0);
+
+        let loaded = Err("scene.txt:3: expected a number, got x".to_string());
+        assert!(replace_scene(&mut scene, &mut selected, &mut history, loaded).is_err());
+        assert!(scene == one_sphere());
+        assert_eq!(selected, Some(0));
+    }
+
+    #[test]
+    fn reload_drops_a_selection_that_is_gone() {
+        let mut scene = one_sphere();
+        let mut history = History::new(&scene, camera(), Settings::default(), 1);
+        let mut selected = Some(0);
+
+        let loaded = Ok(Scene::default());
+        assert!(replace_scene(&mut scene, &mut selected, &mut history, loaded).is_ok());
+        assert_eq!(selected, None);
     }
 }
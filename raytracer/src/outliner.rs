@@ -0,0 +1,177 @@
+use crate::scene::{NamedMaterial, Object, Scene, Shape};
+use glam::Vec3;
+use nannou_egui::egui;
+use shared::{Color, Material};
+
+// The objects scene_world in shared/src/render.rs adds after the scene file ones.
+// They're CSG and SDF shapes the scene file can't describe yet, so they get
+// listed but can't be picked or edited.
+const BUILT_IN: [&str; 4] = [
+    "Lens (CSG)",
+    "Hollow sphere (CSG)",
+    "Mandelbulb (SDF)",
+    "Blob (SDF)",
+];
+
+// What the outliner did, so the app knows how much to upload
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SceneChange {
+    Nothing,
+    // Same objects, different numbers
+    Edited,
+    // Objects got added or removed, the buffers need remaking
+    Resized,
+}
+
+// List of every object with buttons to add and remove them, and fields for the
+// selected one
+pub fn outliner(ui: &mut egui::Ui, scene: &mut Scene, selected: &mut Option<usize>) -> SceneChange {
+    let mut change = SceneChange::Nothing;
+
+    egui::ScrollArea::vertical()
+        .max_height(200.0)
+        .show(ui, |ui| {
+            for (i, object) in scene.objects.iter().enumerate() {
+                let material = &scene.materials[object.material];
+                let light = if material.material.emission > 0.0 {
+                    " (light)"
+                } else {
+                    ""
+                };
+                let text = format!("{} {i}: {}{light}", object.shape.name(), material.name);
+                if ui.selectable_label(*selected == Some(i), text).clicked() {
+                    *selected = Some(i);
+                }
+            }
+            for name in BUILT_IN {
+                ui.weak(format!("{name}, built in"));
+            }
+        });
+
+    ui.horizontal(|ui| {
+        let shapes = [
+            Shape::Sphere {
+                center: Vec3::new(0.0, 0.5, 0.0),
+                radius: 0.5,
+            },
+            Shape::Cuboid {
+                min: Vec3::new(-0.25, 0.0, -0.25),
+                max: Vec3::new(0.25, 0.5, 0.25),
+                rotation: 0.0,
+            },
+            Shape::Plane { y: 0.0 },
+        ];
+        for shape in shapes {
+            if ui.button(format!("Add {}", shape.name())).clicked() {
+                if scene.materials.is_empty() {
+                    scene.materials.push(NamedMaterial {
+                        name: "white".to_string(),
+                        material: Material {
+                            color: Color::new(1.0, 1.0, 1.0),
+                            ..Default::default()
+                        },
+                    });
+                }
+                scene.objects.push(Object { shape, material: 0 });
+                *selected = Some(scene.objects.len() - 1);
+                change = SceneChange::Resized;
+            }
+        }
+    });
+
+    let Some(i) = *selected else {
+        return change;
+    };
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label(format!("{} {i}", scene.objects[i].shape.name()));
+        if ui.button("Duplicate").clicked() {
+            scene.objects.push(scene.objects[i]);
+            *selected = Some(scene.objects.len() - 1);
+            change = SceneChange::Resized;
+        }
+        if ui.button("Delete").clicked() {
+            scene.objects.remove(i);
+            *selected = None;
+            change = SceneChange::Resized;
+        }
+        if ui.button("Deselect").clicked() {
+            *selected = None;
+        }
+    });
+
+    if let Some(i) = *selected {
+        if object_editor(ui, &mut scene.objects[i], &scene.materials)
+            && change == SceneChange::Nothing
+        {
+            change = SceneChange::Edited;
+        }
+    }
+
+    change
+}
+
+// Fields for the shape and material of one object, says if anything changed
+fn object_editor(ui: &mut egui::Ui, object: &mut Object, materials: &[NamedMaterial]) -> bool {
+    let mut changed = false;
+
+    match &mut object.shape {
+        Shape::Sphere { center, radius } => {
+            changed |= vec3_editor(ui, "Center", center);
+            ui.horizontal(|ui| {
+                ui.label("Radius");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(radius)
+                            .speed(0.01)
+                            .clamp_range(0.001..=f32::MAX),
+                    )
+                    .changed();
+            });
+        }
+        Shape::Cuboid { min, max, rotation } => {
+            changed |= vec3_editor(ui, "Min", min);
+            changed |= vec3_editor(ui, "Max", max);
+            ui.horizontal(|ui| {
+                ui.label("Rotation");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(rotation)
+                            .suffix("°")
+                            .clamp_range(-180.0..=180.0),
+                    )
+                    .changed();
+            });
+        }
+        Shape::Plane { y } => {
+            ui.horizontal(|ui| {
+                ui.label("Height");
+                changed |= ui.add(egui::DragValue::new(y).speed(0.01)).changed();
+            });
+        }
+    }
+
+    egui::ComboBox::from_label("Material")
+        .selected_text(materials[object.material].name.as_str())
+        .show_ui(ui, |ui| {
+            for (i, m) in materials.iter().enumerate() {
+                changed |= ui
+                    .selectable_value(&mut object.material, i, m.name.as_str())
+                    .changed();
+            }
+        });
+
+    changed
+}
+
+fn vec3_editor(ui: &mut egui::Ui, label: &str, v: &mut Vec3) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for value in [&mut v.x, &mut v.y, &mut v.z] {
+            changed |= ui.add(egui::DragValue::new(value).speed(0.01)).changed();
+        }
+    });
+    changed
+}
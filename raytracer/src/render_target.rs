@@ -0,0 +1,63 @@
+use nannou::prelude::*;
+
+// Render scale limits, as a fraction of the window size
+pub const MIN_SCALE: f32 = 0.25;
+pub const MAX_SCALE: f32 = 2.0;
+
+// Texture the raytracer draws into at the render resolution, which then gets
+// stretched over the window so the resolution can be lower or higher than it
+pub struct RenderTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    reshaper: wgpu::TextureReshaper,
+}
+
+impl RenderTarget {
+    pub fn new(device: &wgpu::Device, size: [u32; 2], window_samples: u32) -> Self {
+        let texture = wgpu::TextureBuilder::new()
+            .size(size)
+            .format(Frame::TEXTURE_FORMAT)
+            .usage(
+                wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    // Screenshots copy it back
+                    | wgpu::TextureUsages::COPY_SRC,
+            )
+            .build(device);
+        let view = texture.view().build();
+        let reshaper = wgpu::TextureReshaper::new(
+            device,
+            &view,
+            1,
+            texture.sample_type(),
+            window_samples,
+            Frame::TEXTURE_FORMAT,
+        );
+
+        Self {
+            texture,
+            view,
+            reshaper,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.texture.size()
+    }
+
+    // Draws the render onto the window's frame, scaling it to fit
+    pub fn draw_to_frame(&self, frame: &Frame) {
+        let mut encoder = frame.command_encoder();
+        self.reshaper
+            .encode_render_pass(frame.texture_view(), &mut encoder);
+    }
+}
+
+// Render resolution for a window size in pixels, never less than one pixel
+pub fn scaled_size(window: (u32, u32), scale: f32) -> [u32; 2] {
+    let scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+    [
+        ((window.0 as f32 * scale).round() as u32).max(1),
+        ((window.1 as f32 * scale).round() as u32).max(1),
+    ]
+}
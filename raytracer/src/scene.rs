@@ -0,0 +1,391 @@
+use glam::Vec3;
+use shared::{
+    Color, Material, MaterialData, SceneObject, BSDF_CONDUCTOR, BSDF_DIELECTRIC, BSDF_DIFFUSE,
+    BSDF_EMISSIVE, BSDF_MIX, BSDF_PLASTIC, BSDF_SUBSURFACE,
+};
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shape {
+    Sphere { center: Vec3, radius: f32 },
+    // Turned by rotation degrees around the vertical axis through its center
+    Cuboid { min: Vec3, max: Vec3, rotation: f32 },
+    Plane { y: f32 },
+}
+
+impl Shape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shape::Sphere { .. } => "Sphere",
+            Shape::Cuboid { .. } => "Cuboid",
+            Shape::Plane { .. } => "Plane",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Object {
+    pub shape: Shape,
+    // Index into Scene::materials
+    pub material: usize,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct NamedMaterial {
+    pub name: String,
+    pub material: Material,
+}
+
+// Materials and objects from a scene file, see scene.txt for the format
+#[derive(Clone, Default, PartialEq)]
+pub struct Scene {
+    pub materials: Vec<NamedMaterial>,
+    pub objects: Vec<Object>,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("can't read {}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|(line, err)| format!("{}:{line}: {err}", path.display()))
+    }
+
+    // Errors come with their line number
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut scene = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.split('#').next().unwrap().split_whitespace();
+            let Some(keyword) = words.next() else {
+                continue;
+            };
+            let words: Vec<&str> = words.collect();
+
+            match keyword {
+                "material" => {
+                    let material = parse_material(&words).map_err(|err| (i + 1, err))?;
+                    scene.materials.push(material);
+                }
+                "sphere" | "cuboid" | "plane" => {
+                    let object = scene.parse_object(keyword, &words);
+                    scene.objects.push(object.map_err(|err| (i + 1, err))?);
+                }
+                _ => return Err((i + 1, format!("unknown keyword {keyword}"))),
+            }
+        }
+
+        Ok(scene)
+    }
+
+    fn parse_object(&self, keyword: &str, words: &[&str]) -> Result<Object, String> {
+        // Cuboids can have a rotation after the corners
+        let counts = match keyword {
+            "sphere" => 4..=4,
+            "cuboid" => 6..=7,
+            _ => 1..=1,
+        };
+        let (material, numbers) = match words.split_last() {
+            Some((material, numbers)) if counts.contains(&numbers.len()) => (material, numbers),
+            _ => {
+                return Err(format!(
+                    "{keyword} needs {} numbers and a material",
+                    counts.start()
+                ))
+            }
+        };
+        let v = numbers
+            .iter()
+            .map(|n| parse_f32(n))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let shape = match keyword {
+            "sphere" => Shape::Sphere {
+                center: Vec3::new(v[0], v[1], v[2]),
+                radius: v[3],
+            },
+            "cuboid" => Shape::Cuboid {
+                min: Vec3::new(v[0], v[1], v[2]),
+                max: Vec3::new(v[3], v[4], v[5]),
+                rotation: v.get(6).copied().unwrap_or(0.0),
+            },
+            _ => Shape::Plane { y: v[0] },
+        };
+
+        let material = self
+            .materials
+            .iter()
+            .position(|m| m.name == *material)
+            .ok_or_else(|| format!("no material called {material}"))?;
+
+        Ok(Object { shape, material })
+    }
+
+    // Writes the scene back in the same format, comments in the file are lost.
+    // The text gets parsed first so the app never saves a file it can't load.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let text = self.to_text();
+        Self::parse(&text).map_err(|(line, err)| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("line {line} would be wrong: {err}"),
+            )
+        })?;
+        std::fs::write(path, text)
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = String::from(FORMAT_HELP);
+
+        out += "\n";
+        for m in &self.materials {
+            out += &format!("material {}{}\n", m.name, material_properties(&m.material));
+        }
+
+        out += "\n";
+        for object in &self.objects {
+            let material = &self.materials[object.material].name;
+            out += &match object.shape {
+                Shape::Sphere { center, radius } => format!(
+                    "sphere {} {} {} {radius} {material}\n",
+                    center.x, center.y, center.z
+                ),
+                Shape::Cuboid { min, max, rotation } => {
+                    let mut line = format!(
+                        "cuboid {} {} {} {} {} {}",
+                        min.x, min.y, min.z, max.x, max.y, max.z
+                    );
+                    if rotation != 0.0 {
+                        line += &format!(" {rotation}");
+                    }
+                    format!("{line} {material}\n")
+                }
+                Shape::Plane { y } => format!("plane {y} {material}\n"),
+            };
+        }
+
+        out
+    }
+
+    // What goes in the gpu buffers, never empty
+    pub fn gpu_objects(&self) -> Vec<SceneObject> {
+        let mut objects: Vec<SceneObject> = self
+            .objects
+            .iter()
+            .map(|object| {
+                let material = object.material as u32;
+                match object.shape {
+                    Shape::Sphere { center, radius } => {
+                        SceneObject::sphere(center, radius, material)
+                    }
+                    Shape::Cuboid { min, max, rotation } => {
+                        SceneObject::cuboid(min, max, rotation.to_radians(), material)
+                    }
+                    Shape::Plane { y } => SceneObject::plane(y, material),
+                }
+            })
+            .collect();
+        if objects.is_empty() {
+            objects.push(SceneObject::plane(0.0, 0));
+        }
+        objects
+    }
+
+    pub fn gpu_materials(&self) -> Vec<MaterialData> {
+        let mut materials: Vec<MaterialData> = self
+            .materials
+            .iter()
+            .map(|m| MaterialData::new(&m.material))
+            .collect();
+        if materials.is_empty() {
+            materials.push(MaterialData::new(&Material::default()));
+        }
+        materials
+    }
+}
+
+// Names for the material types in scene files and the gui
+pub const MATERIAL_TYPES: [(u32, &str); 7] = [
+    (BSDF_DIFFUSE, "diffuse"),
+    (BSDF_CONDUCTOR, "conductor"),
+    (BSDF_DIELECTRIC, "dielectric"),
+    (BSDF_PLASTIC, "plastic"),
+    (BSDF_EMISSIVE, "emissive"),
+    (BSDF_MIX, "mix"),
+    (BSDF_SUBSURFACE, "subsurface"),
+];
+
+// Type for material lines without one, how materials worked before they had types
+fn default_type(material: &Material) -> u32 {
+    if material.ior > 0.0 {
+        BSDF_DIELECTRIC
+    } else if material.mean_free_path.average() > 0.0 {
+        BSDF_SUBSURFACE
+    } else {
+        BSDF_MIX
+    }
+}
+
+const FORMAT_HELP: &str = "\
+# material <name> [type t] [color r g b] [shininess s] [emission e] [ior n]
+#     [absorption r g b] [absorption_distance d] [priority p] [dispersion b]
+#     [mean_free_path r g b] [roughness r] [metallic m]
+# Types are diffuse, conductor, dielectric, plastic, emissive, mix and subsurface.
+# Without one it's dielectric when there's an ior, subsurface when there's a
+# mean_free_path and otherwise mix, which is diffuse and conductor mixed by shininess.
+# Where dielectrics overlap the one with the highest priority wins.
+# sphere <x> <y> <z> <radius> <material>
+# cuboid <min x> <min y> <min z> <max x> <max y> <max z> [degrees] <material>
+# plane <y> <material>
+";
+
+// Everything that isn't the default, as words for a material line
+fn material_properties(material: &Material) -> String {
+    let default = Material::default();
+    let mut out = String::new();
+
+    if material.kind != default_type(material) {
+        if let Some((_, name)) = MATERIAL_TYPES.iter().find(|(k, _)| *k == material.kind) {
+            out += &format!(" type {name}");
+        }
+    }
+
+    let mut color = |key: &str, color: Color, default: Color| {
+        if [color.r(), color.g(), color.b()] != [default.r(), default.g(), default.b()] {
+            out += &format!(" {key} {} {} {}", color.r(), color.g(), color.b());
+        }
+    };
+    color("color", material.color, default.color);
+    color("absorption", material.absorption, default.absorption);
+    color(
+        "mean_free_path",
+        material.mean_free_path,
+        default.mean_free_path,
+    );
+
+    let numbers = [
+        ("shininess", material.shininess, default.shininess),
+        ("emission", material.emission, default.emission),
+        ("ior", material.ior, default.ior),
+        (
+            "absorption_distance",
+            material.absorption_distance,
+            default.absorption_distance,
+        ),
+        (
+            "priority",
+            material.priority as f32,
+            default.priority as f32,
+        ),
+        ("dispersion", material.dispersion, default.dispersion),
+        ("roughness", material.roughness, default.roughness),
+        ("metallic", material.metallic, default.metallic),
+    ];
+    for (key, value, default) in numbers {
+        if value != default {
+            out += &format!(" {key} {value}");
+        }
+    }
+    out
+}
+
+fn parse_material(words: &[&str]) -> Result<NamedMaterial, String> {
+    let (name, mut words) = words
+        .split_first()
+        .ok_or_else(|| "material needs a name".to_string())?;
+    let mut material = Material::default();
+    let mut kind = None;
+
+    while let Some((key, rest)) = words.split_first() {
+        if *key == "type" {
+            let name = rest.first().ok_or("type needs a name")?;
+            let (k, _) = MATERIAL_TYPES
+                .iter()
+                .find(|(_, n)| n == name)
+                .ok_or_else(|| format!("unknown material type {name}"))?;
+            kind = Some(*k);
+            words = &rest[1..];
+            continue;
+        }
+
+        let count = match *key {
+            "color" | "absorption" | "mean_free_path" => 3,
+            "shininess"
+            | "emission"
+            | "ior"
+            | "absorption_distance"
+            | "priority"
+            | "dispersion"
+            | "roughness"
+            | "metallic" => 1,
+            _ => return Err(format!("unknown material property {key}")),
+        };
+        if rest.len() < count {
+            return Err(format!("{key} needs {count} numbers"));
+        }
+        let v = rest[..count]
+            .iter()
+            .map(|n| parse_f32(n))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let color = || Color::new(v[0], v[1], v[2]);
+        match *key {
+            "color" => material.color = color(),
+            "absorption" => material.absorption = color(),
+            "mean_free_path" => material.mean_free_path = color(),
+            "shininess" => material.shininess = v[0],
+            "emission" => material.emission = v[0],
+            "ior" => material.ior = v[0],
+            "absorption_distance" => material.absorption_distance = v[0],
+            "priority" => material.priority = v[0] as u32,
+            "dispersion" => material.dispersion = v[0],
+            "roughness" => material.roughness = v[0],
+            _ => material.metallic = v[0],
+        }
+        words = &rest[count..];
+    }
+    material.kind = kind.unwrap_or_else(|| default_type(&material));
+    if material.is_dielectric() && material.ior < 1.0 {
+        return Err("dielectrics need an ior of at least 1".to_string());
+    }
+
+    Ok(NamedMaterial {
+        name: name.to_string(),
+        material,
+    })
+}
+
+fn parse_f32(word: &str) -> Result<f32, String> {
+    word.parse()
+        .map_err(|_| format!("expected a number, got {word}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let scene = Scene::parse(include_str!("../scene.txt")).unwrap();
+        assert!(!scene.materials.is_empty() && !scene.objects.is_empty());
+        assert!(Scene::parse(&scene.to_text()).unwrap() == scene);
+    }
+
+    #[test]
+    fn refuses_to_save_what_it_cant_load() {
+        let mut scene = Scene::parse("material glass ior 1.5").unwrap();
+        scene.materials[0].material.ior = 0.5;
+        let path = std::env::temp_dir().join("raytracer-refused-scene.txt");
+        assert!(scene.save(&path).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn rejects_unknown_materials() {
+        assert_eq!(
+            Scene::parse("sphere 0 0 0 1 nothing")
+                .err()
+                .map(|(line, _)| line),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::animation::Keyframe;
+use crate::image_file::{self, ImageOptions, Layer};
+use crate::settings::{mode_name, Settings, INTEGRATORS, VIEW_MODES};
+use nannou::prelude::*;
+use shared::{Camera, Color};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+// Screenshots go in here, named after when they were taken
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+// Render target pixels are 4 f16s
+const BYTES_PER_PIXEL: u32 = 8;
+
+// Renders one frame with the linear pipeline into a texture of its own and reads
+// it back. push_constants are the ShaderConsts as bytes.
+pub fn capture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    push_constants: &[u8],
+    size: [u32; 2],
+) -> Result<Vec<Color>, String> {
+    let texture = wgpu::TextureBuilder::new()
+        .size(size)
+        .format(Frame::TEXTURE_FORMAT)
+        .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC)
+        .build(device);
+    let view = texture.view().build();
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("screenshot render"),
+    });
+    let mut render_pass = wgpu::RenderPassBuilder::new()
+        .color_attachment(&view, |color| color)
+        .begin(&mut encoder);
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.set_push_constants(wgpu::ShaderStages::all(), 0, push_constants);
+    render_pass.draw(0..3, 0..1);
+    drop(render_pass);
+    queue.submit(Some(encoder.finish()));
+
+    read_texture(device, queue, &texture)
+}
+
+// Copies a texture with linear colors back from the gpu
+fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<Color>, String> {
+    let [width, height] = texture.size();
+    // Rows in the buffer have to line up to 256 bytes
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let row_bytes = (width * BYTES_PER_PIXEL).div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("screenshot"),
+        size: (row_bytes * height) as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("screenshot"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(row_bytes),
+                rows_per_image: None,
+            },
+        },
+        texture.extent(),
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .map_err(|err| err.to_string())?
+        .map_err(|err| format!("couldn't read the render back: {err}"))?;
+
+    let data = slice.get_mapped_range();
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for row in data.chunks(row_bytes as usize) {
+        for pixel in row[..(width * BYTES_PER_PIXEL) as usize].chunks(BYTES_PER_PIXEL as usize) {
+            let channel =
+                |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]));
+            pixels.push(Color::new(channel(0), channel(1), channel(2)));
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.0;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-14),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
+
+// What the picture was rendered with, goes in the png as text chunks. Settings
+// gets taken apart field by field so a new setting can't be left out.
+pub fn metadata(scene: &Path, camera: &Camera, settings: &Settings) -> Vec<(String, String)> {
+    let Settings {
+        bounce_limit,
+        background,
+        march_steps,
+        march_epsilon,
+        fog,
+        grid_density,
+        spectral,
+        view_mode,
+        integrator,
+        ao_radius,
+    } = *settings;
+    let color = |c: Color| format!("{} {} {}", c.r(), c.g(), c.b());
+
+    [
+        ("Software", "Locked in raytracer".to_string()),
+        ("Scene", scene.display().to_string()),
+        (
+            "Camera",
+            format!(
+                "{} (x y z yaw pitch fov)",
+                Keyframe::from_camera(camera).to_line()
+            ),
+        ),
+        ("Size", format!("{}x{}", camera.width, camera.height)),
+        ("Samples", camera.samples.to_string()),
+        (
+            "Integrator",
+            mode_name(&INTEGRATORS, integrator).to_string(),
+        ),
+        ("AO radius", ao_radius.to_string()),
+        ("View mode", mode_name(&VIEW_MODES, view_mode).to_string()),
+        ("Bounce limit", bounce_limit.to_string()),
+        ("Spectral", spectral.to_string()),
+        (
+            "Background",
+            format!("{} {} {}", background[0], background[1], background[2]),
+        ),
+        (
+            "Fog",
+            format!(
+                "color {}, absorption {}, scattering {}, g {}, height {}",
+                color(fog.medium.color),
+                fog.medium.absorption,
+                fog.medium.scattering,
+                fog.medium.g,
+                fog.height
+            ),
+        ),
+        (
+            "Ray marching",
+            format!("{march_steps} steps, epsilon {march_epsilon}"),
+        ),
+        ("Grid density", grid_density.to_string()),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+// Saves screenshot_<unix time>.png and optionally a copy in another format like
+// exr with the pixels that go with it, gives back the png path
+pub fn save_screenshot(
+    [width, height]: [u32; 2],
+    pixels: &[Color],
+    metadata: &[(String, String)],
+    options: ImageOptions,
+    hdr: Option<(&str, &[Color])>,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(SCREENSHOT_DIR)
+        .map_err(|err| format!("couldn't create {SCREENSHOT_DIR}: {err}"))?;
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    let path = Path::new(SCREENSHOT_DIR).join(format!("screenshot_{time}.png"));
+
+    let layers = [Layer { name: "", pixels }];
+    image_file::save_image(&path, width, height, &layers, options, metadata)?;
+    if let Some((extension, pixels)) = hdr {
+        let hdr_path = path.with_extension(extension);
+        let layers = [Layer { name: "", pixels }];
+        image_file::save_image(&hdr_path, width, height, &layers, options, &[])?;
+    }
+
+    Ok(path)
+}
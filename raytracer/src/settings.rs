@@ -0,0 +1,112 @@
+use crate::scene::Scene;
+use shared::*;
+
+// Names for the view modes in the gui and on the command line
+pub const VIEW_MODES: [(u32, &str); 8] = [
+    (VIEW_COLOR, "color"),
+    (VIEW_NORMALS, "normals"),
+    (VIEW_DEPTH, "depth"),
+    (VIEW_ALBEDO, "albedo"),
+    (VIEW_BOUNCES, "bounces"),
+    (VIEW_VARIANCE, "variance"),
+    (VIEW_INVALID, "nan"),
+    (VIEW_TESTS, "tests"),
+];
+
+pub const INTEGRATORS: [(u32, &str); 4] = [
+    (INTEGRATOR_PATH, "path"),
+    (INTEGRATOR_DIRECT, "direct"),
+    (INTEGRATOR_AO, "ao"),
+    (INTEGRATOR_WHITTED, "whitted"),
+];
+
+// Name for one of the VIEW_MODES or INTEGRATORS
+pub fn mode_name(modes: &[(u32, &'static str)], mode: u32) -> &'static str {
+    modes
+        .iter()
+        .find(|(m, _)| *m == mode)
+        .map_or(modes[0].1, |(_, name)| name)
+}
+
+// Render settings from the gui, turned into shader constants every frame
+#[derive(Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub bounce_limit: u32,
+    pub background: [f32; 3],
+    pub march_steps: u32,
+    pub march_epsilon: f32,
+    pub fog: Fog,
+    pub grid_density: f32,
+    pub spectral: bool,
+    pub view_mode: u32,
+    pub integrator: u32,
+    pub ao_radius: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bounce_limit: 5,
+            background: [0.0, 0.0, 0.0],
+            march_steps: 128,
+            march_epsilon: 0.001,
+            fog: Fog {
+                medium: Medium {
+                    color: Color::new(1.0, 1.0, 1.0),
+                    absorption: 0.0,
+                    scattering: 0.0,
+                    g: 0.0,
+                },
+                height: 1.0,
+            },
+            grid_density: 1.0,
+            spectral: false,
+            view_mode: VIEW_COLOR,
+            integrator: INTEGRATOR_PATH,
+            ao_radius: 0.5,
+        }
+    }
+}
+
+impl Settings {
+    pub fn consts(
+        &self,
+        camera: &Camera,
+        time: u32,
+        grid: GridInfo,
+        scene: &Scene,
+        selected: Option<usize>,
+    ) -> ShaderConsts {
+        ShaderConsts {
+            time,
+            bounce_limit: self.bounce_limit,
+            width: camera.width,
+            height: camera.height,
+            samples: camera.samples,
+            fov: camera.fov,
+            pos: (camera.pos.x, camera.pos.y, camera.pos.z),
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            background: (self.background[0], self.background[1], self.background[2]),
+            march_steps: self.march_steps,
+            march_epsilon: self.march_epsilon,
+            fog_color: (
+                self.fog.medium.color.r(),
+                self.fog.medium.color.g(),
+                self.fog.medium.color.b(),
+            ),
+            fog_absorption: self.fog.medium.absorption,
+            fog_scattering: self.fog.medium.scattering,
+            fog_g: self.fog.medium.g,
+            fog_height: self.fog.height,
+            grid,
+            grid_density: self.grid_density,
+            spectral: self.spectral as u32,
+            object_count: scene.objects.len() as u32,
+            selected: selected.map_or(NO_SELECTION, |i| i as u32),
+            view_mode: self.view_mode,
+            integrator: self.integrator,
+            ao_radius: self.ao_radius,
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::shader_cache::ShaderCache;
+use nannou::wgpu;
+use spirv_builder::{Capability, MetadataPrintout, ModuleResult, SpirvBuilder};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, SystemTime};
+
+// Running the app with just this argument builds the shader and prints where the
+// spirv went. Hot reload builds in a process like that to get the compiler output.
+pub const BUILD_SHADER_ARG: &str = "--build-shader";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Compiled by build.rs, empty if that couldn't compile it
+const EMBEDDED_SHADER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shaders.spv"));
+// The cache key of the sources build.rs compiled it from
+const EMBEDDED_SHADER_KEY: &str = env!("EMBEDDED_SHADER_KEY");
+
+pub enum ShaderError {
+    // No cargo, or a toolchain without the parts rust-gpu needs
+    ToolchainMissing(String),
+    // rustc or rustc_codegen_spirv didn't like the shader, has the compiler output
+    Codegen(String),
+    Io(PathBuf, std::io::Error),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ToolchainMissing(msg) => write!(f, "Shader toolchain missing: {msg}"),
+            Self::Codegen(output) => write!(f, "Shader didn't compile:\n{}", output.trim_end()),
+            Self::Io(path, err) => write!(f, "Shader build failed on {}: {err}", path.display()),
+        }
+    }
+}
+
+fn crate_dir(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "..", name]
+        .iter()
+        .copied()
+        .collect()
+}
+
+// rust-gpu builds its own std, so it needs cargo and the rust-src component.
+// Runs from this crate so rustup picks the toolchain from its rust-toolchain.toml.
+fn check_toolchain() -> Result<(), ShaderError> {
+    let output = Command::new("rustc")
+        .args(["--print", "sysroot"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .map_err(|err| ShaderError::ToolchainMissing(format!("can't run rustc: {err}")))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ShaderError::ToolchainMissing(stderr.trim().to_string()));
+    }
+
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if !sysroot.join("lib/rustlib/src/rust/library").exists() {
+        return Err(ShaderError::ToolchainMissing(
+            "no rust-src, install it with `rustup component add rust-src`".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Builds the shaders crate, returns the path of the spirv module.
+// The compiler output goes straight to stderr.
+pub fn build_shader() -> Result<PathBuf, ShaderError> {
+    check_toolchain()?;
+
+    let compile_res = SpirvBuilder::new(crate_dir("shaders"), "spirv-unknown-vulkan1.1")
+        .print_metadata(MetadataPrintout::None)
+        .capability(Capability::Int8)
+        .build()
+        .map_err(|err| ShaderError::Codegen(format!("{err}, see the compiler output above")))?;
+
+    match compile_res.module {
+        ModuleResult::SingleModule(path) => Ok(path),
+        ModuleResult::MultiModule(_) => Err(ShaderError::Codegen(
+            "got a module per entry point instead of one".to_string(),
+        )),
+    }
+}
+
+fn cache() -> ShaderCache {
+    ShaderCache::new(
+        Path::new(env!("CARGO_MANIFEST_DIR")),
+        Path::new(env!("OUT_DIR")),
+    )
+}
+
+pub fn compile_shader() -> Result<Vec<u8>, ShaderError> {
+    if let Some(data) = cache().load() {
+        return Ok(data);
+    }
+
+    let path = build_shader()?;
+    let data = read(&path)?;
+    save_cached(&data);
+    Ok(data)
+}
+
+// Same as compile_shader but in a child process, so the errors can be captured
+fn compile_shader_in_child() -> Result<Vec<u8>, ShaderError> {
+    if let Some(data) = cache().load() {
+        return Ok(data);
+    }
+    check_toolchain()?;
+
+    let exe = std::env::current_exe().map_err(|err| ShaderError::Io(PathBuf::new(), err))?;
+    let output = Command::new(&exe)
+        .arg(BUILD_SHADER_ARG)
+        .output()
+        .map_err(|err| ShaderError::Io(exe, err))?;
+
+    if !output.status.success() {
+        return Err(ShaderError::Codegen(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let data = read(Path::new(stdout.lines().last().unwrap_or("").trim()))?;
+    save_cached(&data);
+    Ok(data)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, ShaderError> {
+    std::fs::read(path).map_err(|err| ShaderError::Io(path.to_path_buf(), err))
+}
+
+// Not having a cache isn't worth stopping for, so errors only get printed
+fn save_cached(data: &[u8]) {
+    if let Err(err) = cache().save(data) {
+        eprintln!("Couldn't cache the shader: {err}");
+    }
+}
+
+// Uses the shader from the build if the sources haven't changed since, otherwise
+// compiles it and falls back to the last one that compiled with the same layout
+// if that fails. The error comes along with a fallback so the app can show it's
+// running an older shader.
+pub fn load_shader() -> Result<(Vec<u8>, Option<ShaderError>), ShaderError> {
+    if !EMBEDDED_SHADER.is_empty() && cache().key() == EMBEDDED_SHADER_KEY {
+        return Ok((EMBEDDED_SHADER.to_vec(), None));
+    }
+
+    match compile_shader() {
+        Ok(data) => Ok((data, None)),
+        Err(err) => {
+            eprintln!("{err}");
+            let Some((path, data)) = cache().latest() else {
+                return Err(err);
+            };
+            eprintln!("Using the cached shader from {}", path.display());
+            Ok((data, Some(err)))
+        }
+    }
+}
+
+pub fn shader_desc(data: &[u8]) -> wgpu::ShaderModuleDescriptor<'static> {
+    let source = match wgpu::util::make_spirv(data) {
+        wgpu::ShaderSource::SpirV(cow) => wgpu::ShaderSource::SpirV(Cow::Owned(cow.into_owned())),
+        _ => panic!("Unexpected shader source"),
+    };
+    wgpu::ShaderModuleDescriptor {
+        label: Some("shader desc"),
+        source,
+    }
+}
+
+pub enum ShaderEvent {
+    Compiling,
+    Compiled(Vec<u8>),
+    Failed(ShaderError),
+}
+
+// Watches the shader sources on another thread and rebuilds when something changes
+pub struct ShaderWatcher {
+    receiver: Receiver<ShaderEvent>,
+}
+
+impl ShaderWatcher {
+    pub fn spawn() -> Self {
+        let (sender, receiver) = channel();
+
+        std::thread::spawn(move || {
+            let mut last = snapshot();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+
+                let current = snapshot();
+                if current == last {
+                    continue;
+                }
+                last = current;
+
+                if sender.send(ShaderEvent::Compiling).is_err() {
+                    break;
+                }
+                let event = match compile_shader_in_child() {
+                    Ok(data) => ShaderEvent::Compiled(data),
+                    Err(err) => ShaderEvent::Failed(err),
+                };
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { receiver }
+    }
+
+    pub fn poll(&self) -> Option<ShaderEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+// Every source file with when it was last changed
+fn snapshot() -> Vec<(PathBuf, SystemTime)> {
+    let mut files = Vec::new();
+    for dir in ["shaders", "shared"] {
+        collect_files(&crate_dir(dir).join("src"), &mut files);
+    }
+    files.sort();
+    files
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, SystemTime)>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if let Ok(modified) = entry.metadata().and_then(|meta| meta.modified()) {
+            files.push((path, modified));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+// The files with the entry point and everything the host shares with the shader:
+// ShaderConsts, the storage buffer structs and GridInfo. Shaders built from the
+// same versions of these take the same push constants and buffers.
+const LAYOUT_FILES: [&str; 4] = [
+    "shaders/src/lib.rs",
+    "shared/src/lib.rs",
+    "shared/src/scene.rs",
+    "shared/src/voxel.rs",
+];
+
+// Compiled shaders kept in OUT_DIR/shader-cache, named by a hash of the layout
+// files and one of all the shader sources and the compiler, so unchanged sources
+// never get recompiled. build.rs uses this too through #[path], so it can only
+// use std.
+pub struct ShaderCache {
+    dir: PathBuf,
+    manifest_dir: PathBuf,
+    // The crates that make up the shader
+    pub crates: [PathBuf; 2],
+}
+
+impl ShaderCache {
+    // manifest_dir is the raytracer crate, out_dir its OUT_DIR
+    pub fn new(manifest_dir: &Path, out_dir: &Path) -> Self {
+        Self {
+            dir: out_dir.join("shader-cache"),
+            manifest_dir: manifest_dir.to_path_buf(),
+            crates: [
+                manifest_dir.join("../shaders"),
+                manifest_dir.join("../shared"),
+            ],
+        }
+    }
+
+    // Names the shader the sources as they are now compile to. build.rs passes
+    // this on so the app can tell if the embedded shader is out of date.
+    pub fn key(&self) -> String {
+        format!("{}{:016x}", self.layout_prefix(), self.source_hash())
+    }
+
+    // Where the shader for the sources as they are now goes
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(format!("shaders-{}.spv", self.key()))
+    }
+
+    fn layout_prefix(&self) -> String {
+        let root = self.manifest_dir.join("..");
+        let files = LAYOUT_FILES.map(|file| root.join(file));
+        format!("{:016x}-", hash_files(FNV_OFFSET, &root, &files))
+    }
+
+    // Every source file and manifest of the crates, and the compiler that builds
+    // them. Paths are relative so it doesn't matter where the repo is.
+    fn source_hash(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET, self.compiler().as_bytes());
+        for dir in &self.crates {
+            let mut files = vec![dir.join("Cargo.toml")];
+            collect_files(&dir.join("src"), &mut files);
+            files.sort();
+            hash = hash_files(hash, dir, &files);
+        }
+        hash
+    }
+
+    // The toolchain and the spirv-builder entry from Cargo.lock, which has the
+    // version and the git commit it came from
+    fn compiler(&self) -> String {
+        let toolchain = std::fs::read_to_string(self.manifest_dir.join("rust-toolchain.toml"))
+            .unwrap_or_default();
+        let lock =
+            std::fs::read_to_string(self.manifest_dir.join("Cargo.lock")).unwrap_or_default();
+        let builder = lock
+            .split("[[package]]")
+            .find(|package| package.contains("name = \"spirv-builder\""))
+            .unwrap_or_default();
+        format!("{toolchain}{builder}")
+    }
+
+    pub fn load(&self) -> Option<Vec<u8>> {
+        std::fs::read(self.path()).ok()
+    }
+
+    pub fn save(&self, data: &[u8]) -> Result<(), String> {
+        let path = self.path();
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| format!("can't create {}: {err}", self.dir.display()))?;
+        std::fs::write(&path, data).map_err(|err| format!("can't write {}: {err}", path.display()))
+    }
+
+    // The shader that compiled last out of the ones with the same layout as the
+    // sources now, a shader from before ShaderConsts or a buffer changed would
+    // read garbage. Only the app falls back to this when the current sources
+    // don't compile.
+    #[allow(dead_code)]
+    pub fn latest(&self) -> Option<(PathBuf, Vec<u8>)> {
+        let prefix = format!("shaders-{}", self.layout_prefix());
+        let newest = std::fs::read_dir(&self.dir)
+            .ok()?
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.starts_with(&prefix) && name.ends_with(".spv")
+            })
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .max()?
+            .1;
+        let data = std::fs::read(&newest).ok()?;
+        Some((newest, data))
+    }
+}
+
+// FNV-1a, unlike DefaultHasher it gives the same hash on every platform and
+// Rust version, so the names stay valid across toolchain updates
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// Lengths go in before each part so moving bytes between a path and its
+// contents changes the hash
+fn hash_files(mut hash: u64, base: &Path, files: &[PathBuf]) -> u64 {
+    for file in files {
+        let name = file.strip_prefix(base).unwrap_or(file);
+        let name = name.to_string_lossy().replace('\\', "/");
+        let data = std::fs::read(file).unwrap_or_default();
+        for part in [name.as_bytes(), data.as_slice()] {
+            hash = fnv1a(hash, &(part.len() as u64).to_le_bytes());
+            hash = fnv1a(hash, part);
+        }
+    }
+    hash
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_matches_the_reference() {
+        assert_eq!(fnv1a(FNV_OFFSET, b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(FNV_OFFSET, b"foobar"), 0x8594_4171_f739_67e8);
+    }
+}
@@ -0,0 +1,200 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::rc::Rc;
+
+// Turns rendered frames into one animation file. Gif and apng are written here,
+// anything else gets raw rgb frames piped into ffmpeg which picks the format
+// from the extension.
+pub enum VideoEncoder {
+    // Frame rate goes with every gif frame
+    Gif(GifEncoder<GifFile>, u32, WriteError),
+    Apng(png::Writer<BufWriter<File>>),
+    Ffmpeg(Child, ChildStdin),
+}
+
+pub struct VideoOptions {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    pub fps: u32,
+    // How many times it plays, 0 plays forever
+    pub loops: u32,
+}
+
+impl VideoEncoder {
+    pub fn new(path: &Path, options: &VideoOptions) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+
+        match extension.as_str() {
+            "gif" => Self::gif(path, options),
+            "apng" => Self::apng(path, options),
+            _ => Self::ffmpeg(path, options),
+        }
+    }
+
+    fn gif(path: &Path, options: &VideoOptions) -> Result<Self, String> {
+        let error = WriteError::default();
+        let mut encoder = GifEncoder::new(GifFile {
+            file: create(path)?,
+            error: error.clone(),
+        });
+
+        // Gifs without a repeat block play once, and the count in it is extra plays
+        let repeat = match options.loops {
+            0 => Some(Repeat::Infinite),
+            1 => None,
+            n => Some(Repeat::Finite((n - 1).min(u16::MAX as u32) as u16)),
+        };
+        if let Some(repeat) = repeat {
+            encoder
+                .set_repeat(repeat)
+                .map_err(|err| format!("can't write {}: {err}", path.display()))?;
+        }
+
+        Ok(Self::Gif(encoder, options.fps, error))
+    }
+
+    fn apng(path: &Path, options: &VideoOptions) -> Result<Self, String> {
+        let error = |err: png::EncodingError| format!("can't write {}: {err}", path.display());
+
+        let mut encoder = png::Encoder::new(create(path)?, options.width, options.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(options.frames, options.loops)
+            .map_err(error)?;
+        encoder
+            .set_frame_delay(1, options.fps.min(u16::MAX as u32) as u16)
+            .map_err(error)?;
+
+        Ok(Self::Apng(encoder.write_header().map_err(error)?))
+    }
+
+    fn ffmpeg(path: &Path, options: &VideoOptions) -> Result<Self, String> {
+        let mut child = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgb24"])
+            .args([
+                "-video_size",
+                &format!("{}x{}", options.width, options.height),
+            ])
+            .args(["-framerate", &options.fps.to_string()])
+            .args(["-i", "-", "-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => format!(
+                    "ffmpeg isn't installed, can't write {}. Use a .gif or .apng file instead",
+                    path.display()
+                ),
+                _ => format!("can't start ffmpeg: {err}"),
+            })?;
+
+        let stdin = child.stdin.take().unwrap();
+        Ok(Self::Ffmpeg(child, stdin))
+    }
+
+    // Frame as tightly packed 8 bit rgb
+    pub fn add_frame(&mut self, width: u32, height: u32, rgb: &[u8]) -> Result<(), String> {
+        match self {
+            Self::Gif(encoder, fps, _) => {
+                let rgba = image::RgbaImage::from_fn(width, height, |x, y| {
+                    let i = ((y * width + x) * 3) as usize;
+                    image::Rgba([rgb[i], rgb[i + 1], rgb[i + 2], 255])
+                });
+                let frame = image::Frame::from_parts(
+                    rgba,
+                    0,
+                    0,
+                    image::Delay::from_numer_denom_ms(1000, *fps),
+                );
+                encoder.encode_frame(frame).map_err(|err| err.to_string())
+            }
+            Self::Apng(writer) => writer.write_image_data(rgb).map_err(|err| err.to_string()),
+            Self::Ffmpeg(_, stdin) => stdin
+                .write_all(rgb)
+                .map_err(|err| format!("ffmpeg stopped taking frames: {err}")),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            // Trailer gets written when it's dropped
+            Self::Gif(encoder, _, error) => {
+                drop(encoder);
+                match error.take() {
+                    Some(err) => Err(format!("couldn't finish the gif: {err}")),
+                    None => Ok(()),
+                }
+            }
+            Self::Apng(writer) => writer.finish().map_err(|err| err.to_string()),
+            Self::Ffmpeg(mut child, stdin) => {
+                // Closing stdin tells ffmpeg there are no more frames
+                drop(stdin);
+                let status = child
+                    .wait()
+                    .map_err(|err| format!("ffmpeg didn't finish: {err}"))?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(format!("ffmpeg failed with {status}"))
+                }
+            }
+        }
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|err| format!("can't create {}: {err}", path.display()))
+}
+
+// First error writing a gif
+type WriteError = Rc<RefCell<Option<std::io::Error>>>;
+
+// Gif encoders write the end of the file when they're dropped and throw away any
+// error, so the file keeps the first one where finish can still see it
+pub struct GifFile {
+    file: BufWriter<File>,
+    error: WriteError,
+}
+
+impl GifFile {
+    fn keep<T>(&self, result: std::io::Result<T>) -> std::io::Result<T> {
+        if let Err(err) = &result {
+            let mut error = self.error.borrow_mut();
+            if error.is_none() {
+                *error = Some(std::io::Error::new(err.kind(), err.to_string()));
+            }
+        }
+        result
+    }
+}
+
+impl Write for GifFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let result = self.file.write(buf);
+        self.keep(result)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let result = self.file.flush();
+        self.keep(result)
+    }
+}
+
+impl Drop for GifFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -0,0 +1,226 @@
+use shared::GridInfo;
+use std::path::Path;
+
+// Dense voxel grid loaded from a small text header next to a raw data file:
+//
+//     dims 64 64 64
+//     bounds -1 0 -1 1 2 1
+//     format f32
+//     data cloud.raw
+//
+// The data is little endian with x changing fastest. Format is f32 or u8,
+// u8 values get mapped to 0..1. The data path is relative to the header.
+pub struct VoxelGrid {
+    pub info: GridInfo,
+    pub density: Vec<f32>,
+}
+
+impl VoxelGrid {
+    // Nothing to render, still one voxel so the buffer isn't empty
+    pub fn empty() -> Self {
+        Self {
+            info: GridInfo::empty(),
+            density: vec![0.0],
+        }
+    }
+
+    // Grid given on the command line, or nothing
+    pub fn from_arg(path: Option<&Path>) -> Result<Self, String> {
+        match path {
+            Some(path) => Self::load(path)
+                .map_err(|err| format!("Couldn't load volume {}: {err}", path.display())),
+            None => Ok(Self::empty()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("can't read {}: {err}", path.display()))?;
+        let header = Header::parse(&text).map_err(|(line, err)| match line {
+            0 => format!("{}: {err}", path.display()),
+            _ => format!("{}:{line}: {err}", path.display()),
+        })?;
+
+        let data_path = path.parent().unwrap_or(Path::new(".")).join(&header.data);
+        let bytes = std::fs::read(&data_path)
+            .map_err(|err| format!("can't read {}: {err}", data_path.display()))?;
+
+        let density: Vec<f32> = match header.format {
+            Format::F32 => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Format::U8 => bytes.iter().map(|&b| b as f32 / 255.0).collect(),
+        };
+
+        let dims = header.dims;
+        let count = dims.0 as usize * dims.1 as usize * dims.2 as usize;
+        if density.len() != count {
+            return Err(format!(
+                "{} has {} voxels but dims say {count}",
+                data_path.display(),
+                density.len()
+            ));
+        }
+
+        if let Some(i) = density.iter().position(|d| d.is_nan() || *d < 0.0) {
+            return Err(format!(
+                "{} has density {} at voxel {i}, they can't be negative",
+                data_path.display(),
+                density[i]
+            ));
+        }
+
+        let max_density = density.iter().copied().fold(0.0, f32::max);
+
+        Ok(Self {
+            info: GridInfo {
+                dims,
+                min: header.min,
+                max: header.max,
+                max_density,
+            },
+            density,
+        })
+    }
+}
+
+enum Format {
+    F32,
+    U8,
+}
+
+// Everything from the header file, the data gets read after
+struct Header {
+    dims: (u32, u32, u32),
+    min: (f32, f32, f32),
+    max: (f32, f32, f32),
+    format: Format,
+    data: String,
+}
+
+impl Header {
+    // Errors come with their line number, 0 when it's about the whole header
+    fn parse(text: &str) -> Result<Self, (usize, String)> {
+        let mut dims = None;
+        let mut bounds = None;
+        let mut format = Format::F32;
+        let mut data = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut parts = line.split_whitespace();
+            let Some(key) = parts.next() else {
+                continue;
+            };
+            let values: Vec<&str> = parts.collect();
+            let err = |msg: &str| (i + 1, msg.to_string());
+
+            match key {
+                "dims" => {
+                    let d = parse_all::<u32>(&values, 3).ok_or_else(|| err("expected 3 sizes"))?;
+                    dims = Some((d[0], d[1], d[2]));
+                }
+                "bounds" => {
+                    let b =
+                        parse_all::<f32>(&values, 6).ok_or_else(|| err("expected 6 numbers"))?;
+                    bounds = Some(((b[0], b[1], b[2]), (b[3], b[4], b[5])));
+                }
+                "format" => {
+                    format = match values.first() {
+                        Some(&"f32") => Format::F32,
+                        Some(&"u8") => Format::U8,
+                        Some(other) => {
+                            return Err(err(&format!("unknown format {other}, expected f32 or u8")))
+                        }
+                        None => return Err(err("missing format")),
+                    };
+                }
+                "data" => {
+                    data = Some(
+                        values
+                            .first()
+                            .ok_or_else(|| err("missing data path"))?
+                            .to_string(),
+                    );
+                }
+                _ => return Err(err(&format!("unknown key {key}"))),
+            }
+        }
+
+        let whole = |msg: &str| (0, msg.to_string());
+        let dims = dims.ok_or_else(|| whole("header has no dims"))?;
+        let (min, max) = bounds.ok_or_else(|| whole("header has no bounds"))?;
+        let data = data.ok_or_else(|| whole("header has no data path"))?;
+        if dims.0 == 0 || dims.1 == 0 || dims.2 == 0 {
+            return Err(whole("dims can't be 0"));
+        }
+        if min.0 >= max.0 || min.1 >= max.1 || min.2 >= max.2 {
+            return Err(whole("bounds need min below max on every axis"));
+        }
+
+        Ok(Self {
+            dims,
+            min,
+            max,
+            format,
+            data,
+        })
+    }
+}
+
+fn parse_all<T: std::str::FromStr>(values: &[&str], count: usize) -> Option<Vec<T>> {
+    if values.len() != count {
+        return None;
+    }
+    values.iter().map(|v| v.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_line(text: &str) -> Option<usize> {
+        Header::parse(text).err().map(|(line, _)| line)
+    }
+
+    #[test]
+    fn parses_a_header() {
+        let header =
+            Header::parse("# cloud\ndims 4 2 1\nbounds -1 0 -1 1 2 1\nformat u8\ndata cloud.raw\n")
+                .unwrap();
+        assert_eq!(header.dims, (4, 2, 1));
+        assert_eq!(header.min, (-1.0, 0.0, -1.0));
+        assert_eq!(header.max, (1.0, 2.0, 1.0));
+        assert!(matches!(header.format, Format::U8));
+        assert_eq!(header.data, "cloud.raw");
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        let bounds = "bounds 0 0 0 1 1 1\n";
+        let data = "data a.raw\n";
+
+        // Bad lines
+        assert_eq!(error_line(&format!("dims 4 4\n{bounds}{data}")), Some(1));
+        assert_eq!(error_line(&format!("dims 4 4 x\n{bounds}{data}")), Some(1));
+        assert_eq!(error_line("bounds 0 0 0 1 1\n"), Some(1));
+        assert_eq!(error_line("format f64\n"), Some(1));
+        assert_eq!(error_line("data\n"), Some(1));
+        assert_eq!(error_line("size 4 4 4\n"), Some(1));
+
+        // Missing or wrong as a whole
+        assert_eq!(error_line(&format!("{bounds}{data}")), Some(0));
+        assert_eq!(error_line(&format!("dims 4 4 4\n{data}")), Some(0));
+        assert_eq!(error_line(&format!("dims 4 4 4\n{bounds}")), Some(0));
+        assert_eq!(error_line(&format!("dims 4 0 4\n{bounds}{data}")), Some(0));
+        assert_eq!(
+            error_line(&format!("dims 4 4 4\nbounds 0 0 0 1 0 1\n{data}")),
+            Some(0)
+        );
+        assert_eq!(
+            error_line(&format!("dims 4 4 4\nbounds 0 2 0 1 1 1\n{data}")),
+            Some(0)
+        );
+    }
+}
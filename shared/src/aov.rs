@@ -0,0 +1,163 @@
+use crate::*;
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+
+// What the viewport shows, ShaderConsts::view_mode is one of these
+pub const VIEW_COLOR: u32 = 0;
+pub const VIEW_NORMALS: u32 = 1;
+pub const VIEW_DEPTH: u32 = 2;
+pub const VIEW_ALBEDO: u32 = 3;
+pub const VIEW_BOUNCES: u32 = 4;
+pub const VIEW_VARIANCE: u32 = 5;
+pub const VIEW_INVALID: u32 = 6;
+pub const VIEW_TESTS: u32 = 7;
+
+// Hit tests for a camera ray that show up red in the hit test view
+const TESTS_FOR_RED: f32 = 256.0;
+
+// Extra passes for a pixel besides its color. The lighting ones add up to the
+// color: emission is light seen straight away, direct got there after one bounce
+// and indirect after more.
+#[derive(Clone, Copy)]
+pub struct Aovs {
+    // Distance to the first hit, infinite when nothing was hit
+    pub depth: f32,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub emission: Color,
+    pub direct: Color,
+    pub indirect: Color,
+    // Scene file object and material indices, NO_SELECTION when it wasn't one.
+    // The material only gets filled in by pixel_aovs.
+    pub object: u32,
+    pub material: u32,
+    // How many times the ray hit something before it stopped
+    pub bounces: f32,
+    // Variance of how bright the samples were
+    pub variance: f32,
+    // Samples that came out NaN or infinite
+    pub invalid: u32,
+    // Intersection tests for the first camera ray, only counted in the hit test
+    // view. There's no BVH, so every object gets tested and ray marching is
+    // what makes the difference.
+    pub tests: f32,
+}
+
+impl Aovs {
+    pub fn new() -> Self {
+        let black = Color::new(0.0, 0.0, 0.0);
+        Self {
+            depth: f32::INFINITY,
+            position: vec3(0.0, 0.0, 0.0),
+            normal: vec3(0.0, 0.0, 0.0),
+            albedo: black,
+            emission: black,
+            direct: black,
+            indirect: black,
+            object: NO_SELECTION,
+            material: NO_SELECTION,
+            bounces: 0.0,
+            variance: 0.0,
+            invalid: 0,
+            tests: 0.0,
+        }
+    }
+
+    // Where the first hit was and what it looked like
+    pub fn first_hit(&mut self, ray: &Ray, hit: &HitData) {
+        self.depth = hit.t * ray.direction.length();
+        self.position = hit.point;
+        self.normal = hit.normal;
+        self.albedo = hit.material.color;
+        self.object = hit.object;
+    }
+
+    // Same for when the first thing the ray ran into was a medium. There's no
+    // surface there, so the normal just faces back along the ray.
+    pub fn first_volume_hit(&mut self, ray: &Ray, event: &MediumEvent) {
+        self.depth = event.t * ray.direction.length();
+        self.position = event.point;
+        self.normal = -ray.direction.normalize();
+        self.albedo = event.medium.color;
+        self.object = NO_SELECTION;
+    }
+
+    // Light picked up after some number of bounces
+    pub fn add_light(&mut self, bounce: u32, light: Color) {
+        match bounce {
+            0 => self.emission += light,
+            1 => self.direct += light,
+            _ => self.indirect += light,
+        }
+    }
+
+    pub fn light(&self) -> Color {
+        self.emission + self.direct + self.indirect
+    }
+
+    // Adds up the passes that get averaged over samples, the rest come from
+    // the first sample
+    pub fn add_sample(&mut self, other: &Self) {
+        self.albedo += other.albedo;
+        self.emission += other.emission;
+        self.direct += other.direct;
+        self.indirect += other.indirect;
+        self.bounces += other.bounces;
+    }
+
+    pub fn divide_samples(&mut self, samples: f32) {
+        self.albedo /= samples;
+        self.emission /= samples;
+        self.direct /= samples;
+        self.indirect /= samples;
+        self.bounces /= samples;
+    }
+
+    // What a debug view mode shows for the pixel, max_bounces is what the bounce
+    // heatmap goes up to
+    pub fn view(&self, view_mode: u32, max_bounces: u32) -> Color {
+        // These are meant to be seen as they are, so the gamma correction
+        // afterwards gets undone
+        let display = |c: Color| c.powf(2.2);
+        let gray = |v: f32| Color::new(v, v, v);
+
+        match view_mode {
+            VIEW_NORMALS => display(Color::new(
+                self.normal.x * 0.5 + 0.5,
+                self.normal.y * 0.5 + 0.5,
+                self.normal.z * 0.5 + 0.5,
+            )),
+            // Close is bright, nothing hit is black
+            VIEW_DEPTH => display(gray(1.0 / (1.0 + self.depth * 0.25))),
+            VIEW_ALBEDO => self.albedo,
+            VIEW_BOUNCES => display(heat(self.bounces / max_bounces as f32)),
+            VIEW_VARIANCE => display(heat(self.variance.sqrt())),
+            VIEW_TESTS => display(heat(self.tests / TESTS_FOR_RED)),
+            VIEW_INVALID => {
+                if self.invalid > 0 {
+                    display(Color::new(1.0, 0.0, 1.0))
+                } else {
+                    gray(self.light().average() * 0.5)
+                }
+            }
+            _ => self.light(),
+        }
+    }
+}
+
+// Blue for 0 through green to red for 1
+fn heat(t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    Color::new(
+        (2.0 * t - 1.0).max(0.0),
+        1.0 - (2.0 * t - 1.0).abs(),
+        (1.0 - 2.0 * t).max(0.0),
+    )
+}
+
+// NaN or infinite in any channel
+pub fn is_invalid(color: Color) -> bool {
+    let bad = |c: f32| c.is_nan() || c.is_infinite();
+    bad(color.r()) || bad(color.g()) || bad(color.b())
+}
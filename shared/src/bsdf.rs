@@ -0,0 +1,306 @@
+use crate::integrator::orthonormal_basis;
+use crate::*;
+use spirv_std::glam::Vec3;
+use spirv_std::num_traits::Float;
+use spirv_std::num_traits::FloatConst;
+
+// What kind of surface a material is, Material::kind is one of these. rust-gpu
+// can't do enums with data in them, so it's a number and the fields each kind
+// doesn't use are ignored. Every kind can still glow with emission.
+
+// Lambert with color
+pub const BSDF_DIFFUSE: u32 = 0;
+// Metal, reflects tinted by color, roughness blurs it
+pub const BSDF_CONDUCTOR: u32 = 1;
+// Glass and water, ior and absorption. Goes through the DielectricStack the path
+// carries, so glass in glass works.
+pub const BSDF_DIELECTRIC: u32 = 2;
+// Diffuse color under a clear coat with ior (1.5 when it's 0) and roughness
+pub const BSDF_PLASTIC: u32 = 3;
+// Only gives off light, the path ends on it
+pub const BSDF_EMISSIVE: u32 = 4;
+// Diffuse and conductor mixed by shininess, metallic makes it more conductor
+pub const BSDF_MIX: u32 = 5;
+// Light goes in with color and comes back out somewhere else, see random_walk.
+// mean_free_path says how far it gets in each channel.
+pub const BSDF_SUBSURFACE: u32 = 6;
+
+// Clear coat ior when the material doesn't say
+const DEFAULT_COAT_IOR: f32 = 1.5;
+
+// Where a ray goes after hitting a surface. weight is the bsdf times the cosine
+// over the pdf, so it's what the light gets multiplied by. pdf is what
+// Material::pdf gives for the direction. Delta samples come from mirror-like
+// parts eval and pdf don't cover, like smooth reflections and glass, and have a
+// pdf of 0, so do subsurface walks.
+// end means the path stops here, on emissive surfaces, when glass or a
+// subsurface walk loses track of the ray and when a rough reflection goes under
+// the surface.
+pub struct BsdfSample {
+    pub ray: Ray,
+    pub weight: Color,
+    pub pdf: f32,
+    pub delta: bool,
+    pub end: bool,
+}
+
+impl Material {
+    // Picks where the ray goes on to. Every kind goes through here, dielectrics
+    // update the stack and subsurface walks need the world to find the way out.
+    pub fn sample(
+        &self,
+        ray: &Ray,
+        hit_data: &HitData,
+        world: &impl Hittable,
+        dielectrics: &mut DielectricStack,
+        wavelengths: &mut Wavelengths,
+        rng: &mut RandomSauce,
+    ) -> BsdfSample {
+        let wo = -ray.direction.normalize();
+        let normal = hit_data.normal;
+        let black = Color::new(0.0, 0.0, 0.0);
+
+        match self.kind {
+            BSDF_DIELECTRIC => {
+                let (ray, weight, tracked) = dielectrics.scatter(ray, hit_data, wavelengths, rng);
+                return BsdfSample {
+                    ray,
+                    weight,
+                    pdf: 0.0,
+                    delta: true,
+                    end: !tracked,
+                };
+            }
+            // Seen from inside it's just diffuse
+            BSDF_SUBSURFACE if hit_data.front => {
+                let (ray, weight, exited) = random_walk(hit_data, world, rng);
+                return BsdfSample {
+                    ray,
+                    weight,
+                    pdf: 0.0,
+                    delta: false,
+                    end: !exited,
+                };
+            }
+            BSDF_EMISSIVE => {
+                return BsdfSample {
+                    ray: *ray,
+                    weight: black,
+                    pdf: 0.0,
+                    delta: true,
+                    end: true,
+                }
+            }
+            _ => {}
+        }
+
+        let specular = rng.rand_f() < self.specular_weight(wo, normal);
+        // A smooth reflection is the only way light goes, so it's weighted on its own
+        if specular && self.roughness <= 0.0 {
+            return BsdfSample {
+                ray: Ray::new(hit_data.point, (-wo).reflect(normal)),
+                weight: self.specular_color(),
+                pdf: 0.0,
+                delta: true,
+                end: false,
+            };
+        }
+
+        let dir = if specular {
+            self.glossy_dir(wo, normal, rng)
+        } else {
+            diffuse_dir(normal, rng).normalize()
+        };
+        let pdf = self.pdf(wo, dir, normal);
+        // Rough reflections can point under the surface, those go nowhere
+        if dir.dot(normal) <= 0.0 || pdf <= 0.0 {
+            return BsdfSample {
+                ray: *ray,
+                weight: black,
+                pdf: 0.0,
+                delta: false,
+                end: true,
+            };
+        }
+
+        BsdfSample {
+            ray: Ray::new(hit_data.point, dir),
+            weight: self.eval(wo, dir, normal) / pdf,
+            pdf,
+            delta: false,
+            end: false,
+        }
+    }
+
+    // Bsdf times the cosine for light coming in from wi and leaving towards wo,
+    // both pointing away from the surface and wi normalized. The diffuse part and
+    // rough reflections, delta ones are 0.
+    // Subsurface counts as diffuse here, only sample does the walk.
+    pub fn eval(&self, wo: Vec3, wi: Vec3, normal: Vec3) -> Color {
+        let cos = wi.dot(normal);
+        if cos <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let mut value = self.color * (self.diffuse_weight(wo, normal) * cos / f32::PI());
+        if self.roughness > 0.0 {
+            let glossy = self.specular_weight(wo, normal) * self.glossy_pdf(wo, wi, normal);
+            value += self.specular_color() * glossy;
+        }
+        value
+    }
+
+    // Chance of sample picking wi out of the parts eval covers
+    pub fn pdf(&self, wo: Vec3, wi: Vec3, normal: Vec3) -> f32 {
+        let cos = wi.dot(normal);
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = self.diffuse_weight(wo, normal) * cos / f32::PI();
+        if self.roughness > 0.0 {
+            pdf += self.specular_weight(wo, normal) * self.glossy_pdf(wo, wi, normal);
+        }
+        pdf
+    }
+
+    pub fn emit(&self) -> Color {
+        self.color * self.emission
+    }
+
+    pub fn is_dielectric(&self) -> bool {
+        self.kind == BSDF_DIELECTRIC
+    }
+
+    // Chance of the mix kind reflecting
+    fn specular(&self) -> f32 {
+        self.shininess + (1.0 - self.shininess) * self.metallic
+    }
+
+    // How much of the material is diffuse
+    fn diffuse_weight(&self, wo: Vec3, normal: Vec3) -> f32 {
+        match self.kind {
+            BSDF_DIFFUSE | BSDF_SUBSURFACE => 1.0,
+            BSDF_PLASTIC => 1.0 - self.coat_reflectance(wo, normal),
+            BSDF_MIX => 1.0 - self.specular(),
+            _ => 0.0,
+        }
+    }
+
+    // How much of it reflects, delta when the roughness is 0
+    fn specular_weight(&self, wo: Vec3, normal: Vec3) -> f32 {
+        match self.kind {
+            BSDF_CONDUCTOR => 1.0,
+            BSDF_PLASTIC => self.coat_reflectance(wo, normal),
+            BSDF_MIX => self.specular(),
+            _ => 0.0,
+        }
+    }
+
+    // The clear coat doesn't tint its reflections
+    fn specular_color(&self) -> Color {
+        if self.kind == BSDF_PLASTIC {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            self.color
+        }
+    }
+
+    // Schlick's approximation for the clear coat
+    fn coat_reflectance(&self, wo: Vec3, normal: Vec3) -> f32 {
+        let ior = if self.ior > 0.0 {
+            self.ior
+        } else {
+            DEFAULT_COAT_IOR
+        };
+        let r0 = ((1.0 - ior) / (1.0 + ior)) * ((1.0 - ior) / (1.0 + ior));
+        let cos = wo.dot(normal).clamp(0.0, 1.0);
+        r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+    }
+
+    // Rough reflections are a Phong lobe around the mirror direction, sharper the
+    // smoother it is. Its bsdf times the cosine is the lobe's pdf, so sampling it
+    // on its own has a weight of the color.
+    fn glossy_exponent(&self) -> f32 {
+        2.0 / (self.roughness * self.roughness) - 2.0
+    }
+
+    fn glossy_dir(&self, wo: Vec3, normal: Vec3, rng: &mut RandomSauce) -> Vec3 {
+        let mirror = (-wo).reflect(normal);
+        let cos = rng.rand_f().powf(1.0 / (self.glossy_exponent() + 1.0));
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * f32::PI() * rng.rand_f();
+        let (u, v) = orthonormal_basis(mirror);
+        u * (phi.cos() * sin) + v * (phi.sin() * sin) + mirror * cos
+    }
+
+    fn glossy_pdf(&self, wo: Vec3, wi: Vec3, normal: Vec3) -> f32 {
+        let n = self.glossy_exponent();
+        let cos = wi.dot((-wo).reflect(normal)).max(0.0);
+        (n + 1.0) / (2.0 * f32::PI()) * cos.powf(n)
+    }
+}
+
+// Cosine weighted around the normal
+fn diffuse_dir(normal: Vec3, rng: &mut RandomSauce) -> Vec3 {
+    normal + rng.rand_unit_vec3()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv_std::glam::vec3;
+
+    // Samples a ray coming down at 45 degrees onto a floor facing up
+    fn samples(material: Material, count: u32) -> impl Iterator<Item = BsdfSample> {
+        let mut hit_data = HitData::new();
+        hit_data.normal = vec3(0.0, 1.0, 0.0);
+        hit_data.front = true;
+        hit_data.material = material;
+        let ray = Ray::new(vec3(-1.0, 1.0, 0.0), vec3(1.0, -1.0, 0.0));
+        let world = Plane { y: -10.0, material };
+        let mut rng = RandomSauce { state: 7 };
+
+        (0..count).map(move |_| {
+            material.sample(
+                &ray,
+                &hit_data,
+                &world,
+                &mut DielectricStack::new(),
+                &mut Wavelengths::rgb(),
+                &mut rng,
+            )
+        })
+    }
+
+    fn metal(roughness: f32) -> Material {
+        Material {
+            color: Color::new(0.9, 0.6, 0.3),
+            roughness,
+            kind: BSDF_CONDUCTOR,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn smooth_metal_is_a_mirror() {
+        for sample in samples(metal(0.0), 16) {
+            assert!(sample.delta && !sample.end);
+            assert!((sample.ray.direction - vec3(1.0, 1.0, 0.0).normalize()).length() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rough_metal_stays_above_the_surface() {
+        let material = metal(0.5);
+        let wo = vec3(-1.0, 1.0, 0.0).normalize();
+        let normal = vec3(0.0, 1.0, 0.0);
+
+        for sample in samples(material, 256).filter(|sample| !sample.end) {
+            let dir = sample.ray.direction;
+            assert!(!sample.delta);
+            assert!(dir.dot(normal) > 0.0 && (dir.length() - 1.0).abs() < 1e-4);
+            assert!((sample.pdf - material.pdf(wo, dir, normal)).abs() < 1e-4);
+            // The lobe is sampled exactly, so only the color is left
+            assert!((sample.weight.r() - 0.9).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{hit_solid, HitData, Hittable, Ray, Solid};
+use spirv_std::num_traits::Float;
+
+// Solids only give one span per query, so the operations keep asking for the
+// next span until they find one that works. Give up after this many tries.
+const MAX_SPANS: u32 = 8;
+
+// Surfaces cut out by a difference face the other way
+fn flipped(mut hit_data: HitData) -> HitData {
+    hit_data.normal = -hit_data.normal;
+    hit_data
+}
+
+#[derive(Copy, Clone)]
+pub struct Union<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: Solid, B: Solid> Solid for Union<A, B> {
+    fn shapes(&self) -> u32 {
+        self.a.shapes() + self.b.shapes()
+    }
+
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool {
+        let mut a_in = HitData::new();
+        let mut a_out = HitData::new();
+        let mut b_in = HitData::new();
+        let mut b_out = HitData::new();
+
+        let has_a = self.a.interval(ray, t_min, &mut a_in, &mut a_out);
+        let has_b = self.b.interval(ray, t_min, &mut b_in, &mut b_out);
+        if !has_a && !has_b {
+            return false;
+        }
+
+        // Start with the span that comes first
+        if has_a && (!has_b || a_in.t <= b_in.t) {
+            *enter = a_in;
+            *exit = a_out;
+        } else {
+            *enter = b_in;
+            *exit = b_out;
+        }
+
+        // Then keep merging in spans that overlap the end of it
+        for _ in 0..MAX_SPANS {
+            let mut grew = false;
+            if self.a.interval(ray, exit.t, &mut a_in, &mut a_out) && a_in.t <= exit.t {
+                *exit = a_out;
+                grew = true;
+            }
+            if self.b.interval(ray, exit.t, &mut b_in, &mut b_out) && b_in.t <= exit.t {
+                *exit = b_out;
+                grew = true;
+            }
+            if !grew {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+impl<A: Solid, B: Solid> Hittable for Union<A, B> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        hit_solid(self, ray, t_min, t_max, hit_data)
+    }
+
+    fn tests(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> u32 {
+        self.shapes()
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Intersection<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: Solid, B: Solid> Solid for Intersection<A, B> {
+    fn shapes(&self) -> u32 {
+        self.a.shapes() + self.b.shapes()
+    }
+
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool {
+        let mut a_in = HitData::new();
+        let mut a_out = HitData::new();
+        let mut b_in = HitData::new();
+        let mut b_out = HitData::new();
+        let mut t = t_min;
+
+        for _ in 0..MAX_SPANS {
+            if !self.a.interval(ray, t, &mut a_in, &mut a_out)
+                || !self.b.interval(ray, t, &mut b_in, &mut b_out)
+            {
+                return false;
+            }
+
+            // Overlap is from the last entry to the first exit
+            *enter = if a_in.t > b_in.t { a_in } else { b_in };
+            *exit = if a_out.t < b_out.t { a_out } else { b_out };
+            if enter.t < exit.t {
+                return true;
+            }
+
+            // No overlap, skip past the span that ends first
+            t = exit.t;
+        }
+
+        false
+    }
+}
+
+impl<A: Solid, B: Solid> Hittable for Intersection<A, B> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        hit_solid(self, ray, t_min, t_max, hit_data)
+    }
+
+    fn tests(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> u32 {
+        self.shapes()
+    }
+}
+
+// a with b cut out of it
+#[derive(Copy, Clone)]
+pub struct Difference<A, B> {
+    pub a: A,
+    pub b: B,
+}
+
+impl<A: Solid, B: Solid> Solid for Difference<A, B> {
+    fn shapes(&self) -> u32 {
+        self.a.shapes() + self.b.shapes()
+    }
+
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool {
+        let mut b_in = HitData::new();
+        let mut b_out = HitData::new();
+        let mut t = t_min;
+        let mut has_a = false;
+
+        for _ in 0..MAX_SPANS {
+            if !has_a {
+                if !self.a.interval(ray, t, enter, exit) {
+                    return false;
+                }
+                has_a = true;
+            }
+
+            let start = enter.t.max(t_min);
+            if !self.b.interval(ray, start, &mut b_in, &mut b_out) || b_in.t >= exit.t {
+                // b doesn't touch the rest of the span
+                return true;
+            }
+            if b_in.t > start {
+                // b cuts the span short
+                *exit = flipped(b_in);
+                return true;
+            }
+
+            if b_out.t >= exit.t {
+                // b covers the whole span, try the next one
+                t = exit.t;
+                has_a = false;
+            } else {
+                // b covers the start of the span, it now starts where b ends
+                *enter = flipped(b_out);
+            }
+        }
+
+        false
+    }
+}
+
+impl<A: Solid, B: Solid> Hittable for Difference<A, B> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        hit_solid(self, ray, t_min, t_max, hit_data)
+    }
+
+    fn tests(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> u32 {
+        self.shapes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Sphere;
+    use spirv_std::glam::vec3;
+
+    // Along the x axis, starting at x = -5
+    fn ray() -> Ray {
+        Ray::new(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0))
+    }
+
+    // Centered on the x axis, so ray goes in at t = 5 + x - radius
+    fn sphere(x: f32, radius: f32) -> Sphere {
+        Sphere {
+            center: vec3(x, 0.0, 0.0),
+            radius,
+            material: HitData::new().material,
+        }
+    }
+
+    fn span(solid: &impl Solid, t_min: f32) -> Option<(f32, f32)> {
+        let mut enter = HitData::new();
+        let mut exit = HitData::new();
+        solid
+            .interval(&ray(), t_min, &mut enter, &mut exit)
+            .then_some((enter.t, exit.t))
+    }
+
+    #[test]
+    fn disjoint() {
+        let (a, b) = (sphere(0.0, 1.0), sphere(4.0, 1.0));
+        assert_eq!(span(&Union { a, b }, 0.0), Some((4.0, 6.0)));
+        assert_eq!(span(&Union { a, b }, 6.5), Some((8.0, 10.0)));
+        assert_eq!(span(&Intersection { a, b }, 0.0), None);
+        assert_eq!(span(&Difference { a, b }, 0.0), Some((4.0, 6.0)));
+    }
+
+    #[test]
+    fn tangent() {
+        let (a, b) = (sphere(0.0, 1.0), sphere(2.0, 1.0));
+        // Touching spans join up, and only share a point otherwise
+        assert_eq!(span(&Union { a, b }, 0.0), Some((4.0, 8.0)));
+        assert_eq!(span(&Intersection { a, b }, 0.0), None);
+        assert_eq!(span(&Difference { a, b }, 0.0), Some((4.0, 6.0)));
+    }
+
+    #[test]
+    fn contained() {
+        let (outer, inner) = (sphere(0.0, 2.0), sphere(0.0, 1.0));
+        let union = Union { a: outer, b: inner };
+        let intersection = Intersection { a: outer, b: inner };
+        assert_eq!(span(&union, 0.0), Some((3.0, 7.0)));
+        assert_eq!(span(&intersection, 0.0), Some((4.0, 6.0)));
+
+        // A hollow shell, the hole's surfaces face into it
+        let shell = Difference { a: outer, b: inner };
+        let mut enter = HitData::new();
+        let mut exit = HitData::new();
+        assert!(shell.interval(&ray(), 0.0, &mut enter, &mut exit));
+        assert_eq!((enter.t, exit.t), (3.0, 4.0));
+        assert_eq!(exit.normal, vec3(1.0, 0.0, 0.0));
+        assert_eq!(span(&shell, 4.5), Some((6.0, 7.0)));
+
+        // Nothing left when the cut is bigger
+        assert_eq!(span(&Difference { a: inner, b: outer }, 0.0), None);
+    }
+}
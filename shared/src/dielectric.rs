@@ -0,0 +1,165 @@
+use crate::{Color, HitData, Material, RandomSauce, Ray, Wavelengths};
+
+// How many dielectrics a ray can be inside of at the same time
+const MAX_NESTING: usize = 4;
+
+// Keeps track of which dielectrics a path is inside of, so touching media like
+// ice in water or liquid in a glass work. The one with the highest priority wins
+// where they overlap, surfaces of the others are ignored there.
+// Entries are told apart by HitData::object. Everything that isn't from the scene
+// file shares NO_SELECTION, so only one of those can be a dielectric.
+#[derive(Copy, Clone)]
+pub struct DielectricStack {
+    materials: [Material; MAX_NESTING],
+    objects: [u32; MAX_NESTING],
+    len: usize,
+}
+
+impl DielectricStack {
+    pub fn new() -> Self {
+        Self {
+            materials: [Material::default(); MAX_NESTING],
+            objects: [0; MAX_NESTING],
+            len: 0,
+        }
+    }
+
+    // Index of the material with the highest priority, len if empty
+    fn top(&self) -> usize {
+        let mut top = self.len;
+        for i in 0..self.len {
+            if top == self.len || self.materials[i].priority > self.materials[top].priority {
+                top = i;
+            }
+        }
+        top
+    }
+
+    // Index of refraction of whatever the ray is in right now
+    pub fn ior(&self, lambda: f32) -> f32 {
+        let top = self.top();
+        if top < self.len {
+            self.materials[top].ior_at(lambda)
+        } else {
+            1.0
+        }
+    }
+
+    fn dispersive(&self) -> bool {
+        let top = self.top();
+        top < self.len && self.materials[top].dispersion > 0.0
+    }
+
+    // False if the ray is already inside too many
+    fn push(&mut self, hit_data: &HitData) -> bool {
+        if self.len >= MAX_NESTING {
+            return false;
+        }
+        self.materials[self.len] = hit_data.material;
+        self.objects[self.len] = hit_data.object;
+        self.len += 1;
+        true
+    }
+
+    fn remove(&mut self, object: u32) {
+        let mut found = false;
+        for i in 0..self.len {
+            if !found && self.objects[i] == object {
+                found = true;
+            }
+            if found && i + 1 < self.len {
+                self.materials[i] = self.materials[i + 1];
+                self.objects[i] = self.objects[i + 1];
+            }
+        }
+        if found {
+            self.len -= 1;
+        }
+    }
+
+    // Beer-Lambert absorption for travelling distance through the current medium
+    pub fn transmittance(&self, distance: f32) -> Color {
+        let top = self.top();
+        if top < self.len {
+            self.materials[top].transmittance(distance)
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    // Scatters off a dielectric surface and updates the stack if the ray went through.
+    // Gives false when the ray goes into more dielectrics than the stack can hold,
+    // the path has to stop then since it can't know where it is anymore.
+    pub fn scatter(
+        &mut self,
+        ray: &Ray,
+        hit_data: &HitData,
+        wavelengths: &mut Wavelengths,
+        rng: &mut RandomSauce,
+    ) -> (Ray, Color, bool) {
+        let material = hit_data.material;
+        let lambda = wavelengths.hero();
+        let pass_through = (
+            Ray::new(hit_data.point, ray.direction),
+            Color::new(1.0, 1.0, 1.0),
+            true,
+        );
+        let lost = (*ray, Color::new(0.0, 0.0, 0.0), false);
+
+        if hit_data.front {
+            // Entering something with lower priority than where we are isn't a real surface
+            let top = self.top();
+            if top < self.len && material.priority < self.materials[top].priority {
+                if !self.push(hit_data) {
+                    return lost;
+                }
+                return pass_through;
+            }
+
+            let n1 = self.ior(lambda);
+            let n2 = material.ior_at(lambda);
+            let dispersive = self.dispersive() || material.dispersion > 0.0;
+            let (scattered, refracted) = material.scatter_dielectric(ray, hit_data, n1, n2, rng);
+            if refracted && !self.push(hit_data) {
+                return lost;
+            }
+            (
+                scattered,
+                material.color * collapse(dispersive, wavelengths),
+                true,
+            )
+        } else {
+            let mut outside = *self;
+            outside.remove(hit_data.object);
+
+            // Leaving something that wasn't the one that counted isn't a real surface either
+            let top = outside.top();
+            if top < outside.len && outside.materials[top].priority > material.priority {
+                *self = outside;
+                return pass_through;
+            }
+
+            let n1 = material.ior_at(lambda);
+            let n2 = outside.ior(lambda);
+            let dispersive = outside.dispersive() || material.dispersion > 0.0;
+            let (scattered, refracted) = material.scatter_dielectric(ray, hit_data, n1, n2, rng);
+            if refracted {
+                *self = outside;
+            }
+            (
+                scattered,
+                material.color * collapse(dispersive, wavelengths),
+                true,
+            )
+        }
+    }
+}
+
+// The direction only depended on the hero wavelength if anything involved disperses
+fn collapse(dispersive: bool, wavelengths: &mut Wavelengths) -> Color {
+    if dispersive {
+        wavelengths.collapse()
+    } else {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
@@ -0,0 +1,247 @@
+use crate::*;
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+use spirv_std::num_traits::FloatConst;
+
+// How light gets worked out, ShaderConsts::integrator is one of these
+pub const INTEGRATOR_PATH: u32 = 0;
+pub const INTEGRATOR_DIRECT: u32 = 1;
+pub const INTEGRATOR_AO: u32 = 2;
+pub const INTEGRATOR_WHITTED: u32 = 3;
+
+// Picks between full path tracing and the cheaper ways of lighting a pixel, which
+// are quicker to preview with and help tell where light comes from.
+// Only path tracing does spectral rendering and scattering in media, the others
+// just get dimmed by them.
+pub struct Integrator {
+    pub kind: u32,
+    pub max_depth: u32,
+    pub background: Color,
+    pub spectral: bool,
+    // How far away something still blocks ambient light
+    pub ao_radius: f32,
+}
+
+impl Integrator {
+    pub fn new(constants: &ShaderConsts) -> Self {
+        Self {
+            kind: constants.integrator,
+            max_depth: constants.bounce_limit,
+            background: Color::new(
+                constants.background.0,
+                constants.background.1,
+                constants.background.2,
+            ),
+            spectral: constants.spectral != 0,
+            ao_radius: constants.ao_radius,
+        }
+    }
+
+    // Light coming back along a ray. scene is the part of world with the lights in it.
+    pub fn radiance(
+        &self,
+        ray: Ray,
+        scene: &ObjectList,
+        world: &impl Hittable,
+        media: &impl Participating,
+        rng: &mut RandomSauce,
+    ) -> Aovs {
+        match self.kind {
+            INTEGRATOR_DIRECT => self.direct(ray, scene, world, media, rng),
+            INTEGRATOR_AO => self.ambient_occlusion(ray, world, rng),
+            INTEGRATOR_WHITTED => self.whitted(ray, scene, world, media, rng),
+            _ => ray_color(
+                ray,
+                world,
+                media,
+                rng,
+                self.max_depth,
+                self.background,
+                self.spectral,
+            ),
+        }
+    }
+
+    // What the first thing hit gives off plus light straight from the lights,
+    // nothing bounces further
+    fn direct(
+        &self,
+        ray: Ray,
+        scene: &ObjectList,
+        world: &impl Hittable,
+        media: &impl Participating,
+        rng: &mut RandomSauce,
+    ) -> Aovs {
+        let mut aovs = Aovs::new();
+        let mut hit_data = HitData::new();
+        if !world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data) {
+            let transmittance = media.transmittance(&ray, 0.0001, f32::INFINITY, rng);
+            aovs.add_light(0, self.background * transmittance);
+            return aovs;
+        }
+
+        aovs.first_hit(&ray, &hit_data);
+        aovs.bounces = 1.0;
+        let transmittance = media.transmittance(&ray, 0.0001, hit_data.t, rng);
+        let lights = sample_lights(&ray, &hit_data, scene, world, media, rng);
+        aovs.add_light(0, hit_data.material.emit() * transmittance);
+        aovs.add_light(1, lights * transmittance);
+        aovs
+    }
+
+    // White where nothing is within ao_radius of the first hit, darker the more
+    // directions are blocked
+    fn ambient_occlusion(&self, ray: Ray, world: &impl Hittable, rng: &mut RandomSauce) -> Aovs {
+        let mut aovs = Aovs::new();
+        let mut hit_data = HitData::new();
+        if !world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data) {
+            aovs.add_light(0, self.background);
+            return aovs;
+        }
+
+        aovs.first_hit(&ray, &hit_data);
+        aovs.bounces = 1.0;
+
+        // Cosine weighted, so the fraction that gets out is already the answer
+        let dir = (hit_data.normal + rng.rand_unit_vec3()).normalize();
+        let mut blocker = HitData::new();
+        let blocked = world.hit(
+            &Ray::new(hit_data.point, dir),
+            0.0001,
+            self.ao_radius,
+            &mut blocker,
+        );
+        if !blocked {
+            aovs.add_light(1, Color::new(1.0, 1.0, 1.0));
+        }
+        aovs
+    }
+
+    // Classic Whitted style: light straight from the lights on every surface,
+    // and the ray keeps going while the bsdf picks a mirror-like bounce. That's
+    // picked at random instead of following both reflection and refraction
+    // through glass, since there's no recursion on the gpu.
+    fn whitted(
+        &self,
+        mut ray: Ray,
+        scene: &ObjectList,
+        world: &impl Hittable,
+        media: &impl Participating,
+        rng: &mut RandomSauce,
+    ) -> Aovs {
+        let mut aovs = Aovs::new();
+        let mut color = Color::new(1.0, 1.0, 1.0);
+        let mut dielectrics = DielectricStack::new();
+        let mut wavelengths = Wavelengths::rgb();
+
+        for bounce in 0..self.max_depth {
+            let mut hit_data = HitData::new();
+            if !world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data) {
+                let transmittance = media.transmittance(&ray, 0.0001, f32::INFINITY, rng);
+                aovs.add_light(bounce, color * self.background * transmittance);
+                break;
+            }
+
+            if bounce == 0 {
+                aovs.first_hit(&ray, &hit_data);
+            }
+            aovs.bounces += 1.0;
+            color *= dielectrics.transmittance(hit_data.t * ray.direction.length())
+                * media.transmittance(&ray, 0.0001, hit_data.t, rng);
+            aovs.add_light(bounce, color * hit_data.material.emit());
+
+            let lights = sample_lights(&ray, &hit_data, scene, world, media, rng);
+            aovs.add_light(bounce + 1, color * lights);
+
+            // Light sampling already covered the diffuse part
+            let sample = hit_data.material.sample(
+                &ray,
+                &hit_data,
+                world,
+                &mut dielectrics,
+                &mut wavelengths,
+                rng,
+            );
+            if sample.end || !sample.delta {
+                break;
+            }
+            ray = sample.ray;
+            color *= sample.weight;
+        }
+
+        aovs
+    }
+}
+
+// Light from every glowing sphere in the scene file that ray's hit reflects
+// back along it, through the non delta part of the material's bsdf. One sample
+// per light, picked from the cone of directions the sphere covers. Other glowing
+// shapes are only found by bouncing into them. Media on the way dim the light.
+fn sample_lights(
+    ray: &Ray,
+    hit_data: &HitData,
+    scene: &ObjectList,
+    world: &impl Hittable,
+    media: &impl Participating,
+    rng: &mut RandomSauce,
+) -> Color {
+    let material = hit_data.material;
+    let wo = -ray.direction.normalize();
+    let mut light = Color::new(0.0, 0.0, 0.0);
+
+    for i in 0..scene.count {
+        let object = scene.objects[i as usize];
+        let emitter = scene.materials[object.material as usize].material();
+        if object.shape != SHAPE_SPHERE || emitter.emission <= 0.0 {
+            continue;
+        }
+
+        let center = object.a.truncate();
+        let radius = object.a.w;
+        let to_center = center - hit_data.point;
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= radius * radius {
+            continue;
+        }
+
+        let cos_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+        let cos_theta = 1.0 + rng.rand_f() * (cos_max - 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * f32::PI() * rng.rand_f();
+        let w = to_center.normalize();
+        let (u, v) = orthonormal_basis(w);
+        let dir = u * (phi.cos() * sin_theta) + v * (phi.sin() * sin_theta) + w * cos_theta;
+
+        let bsdf = material.eval(wo, dir, hit_data.normal);
+        if bsdf.average() <= 0.0 {
+            continue;
+        }
+
+        // Has to reach this light before anything else, another light in the way
+        // gets its own turn
+        let shadow_ray = Ray::new(hit_data.point, dir);
+        let mut shadow = HitData::new();
+        let hit = world.hit(&shadow_ray, 0.0001, f32::INFINITY, &mut shadow);
+        if !hit || shadow.object != i {
+            continue;
+        }
+
+        // The cone's pdf is 1 / solid angle
+        let solid_angle = 2.0 * f32::PI() * (1.0 - cos_max);
+        let transmittance = media.transmittance(&shadow_ray, 0.0001, shadow.t, rng);
+        light += shadow.material.emit() * bsdf * (solid_angle * transmittance);
+    }
+
+    light
+}
+
+// Two directions at right angles to w and each other
+pub(crate) fn orthonormal_basis(w: Vec3) -> (Vec3, Vec3) {
+    let a = if w.x.abs() > 0.9 {
+        vec3(0.0, 1.0, 0.0)
+    } else {
+        vec3(1.0, 0.0, 0.0)
+    };
+    let v = w.cross(a).normalize();
+    (w.cross(v), v)
+}
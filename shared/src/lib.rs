@@ -1,10 +1,44 @@
 #![no_std]
 
+pub use aov::{
+    is_invalid, Aovs, VIEW_ALBEDO, VIEW_BOUNCES, VIEW_COLOR, VIEW_DEPTH, VIEW_INVALID,
+    VIEW_NORMALS, VIEW_TESTS, VIEW_VARIANCE,
+};
+pub use bsdf::{
+    BsdfSample, BSDF_CONDUCTOR, BSDF_DIELECTRIC, BSDF_DIFFUSE, BSDF_EMISSIVE, BSDF_MIX,
+    BSDF_PLASTIC, BSDF_SUBSURFACE,
+};
+pub use csg::{Difference, Intersection, Union};
+pub use dielectric::DielectricStack;
+pub use integrator::{
+    Integrator, INTEGRATOR_AO, INTEGRATOR_DIRECT, INTEGRATOR_PATH, INTEGRATOR_WHITTED,
+};
+pub use medium::{Fog, Medium, MediumEvent, Participating, Volume};
+pub use render::{object_at, pixel_aovs, render_pixel, trace_pixel};
+pub use scene::{
+    MaterialData, ObjectList, SceneObject, NO_SELECTION, SHAPE_CUBOID, SHAPE_PLANE, SHAPE_SPHERE,
+};
+pub use sdf::{Mandelbulb, RayMarched, RoundBox, Sdf, SdfSphere, SmoothUnion};
+pub use spectral::{cauchy_ior, Wavelengths};
 use spirv_std::glam::{vec2, vec3, vec4, Vec2, Vec3, Vec4};
 use spirv_std::num_traits::Float;
 use spirv_std::num_traits::FloatConst;
+pub use subsurface::random_walk;
 pub use utils::Color;
+pub use voxel::{GridInfo, GridVolume};
+mod aov;
+mod bsdf;
+mod csg;
+mod dielectric;
+mod integrator;
+mod medium;
+mod render;
+mod scene;
+mod sdf;
+mod spectral;
+mod subsurface;
 mod utils;
+mod voxel;
 
 #[repr(C)]
 pub struct ShaderConsts {
@@ -18,6 +52,21 @@ pub struct ShaderConsts {
     pub yaw: f32,
     pub pitch: f32,
     pub background: (f32, f32, f32),
+    pub march_steps: u32,
+    pub march_epsilon: f32,
+    pub fog_color: (f32, f32, f32),
+    pub fog_absorption: f32,
+    pub fog_scattering: f32,
+    pub fog_g: f32,
+    pub fog_height: f32,
+    pub grid: GridInfo,
+    pub grid_density: f32,
+    pub spectral: u32,
+    pub object_count: u32,
+    pub selected: u32,
+    pub view_mode: u32,
+    pub integrator: u32,
+    pub ao_radius: f32,
 }
 
 pub struct RandomSauce {
@@ -26,15 +75,20 @@ pub struct RandomSauce {
 
 impl RandomSauce {
     pub fn new(consts: &ShaderConsts, coords: Vec4) -> Self {
-        let mut state = coords.x.to_bits() + coords.y.to_bits() * 10000;
-        state ^= consts.time * 1337;
+        // Wrapping like the gpu does, so the cpu gets the same numbers without
+        // overflow panics in debug builds
+        let mut state = coords
+            .x
+            .to_bits()
+            .wrapping_add(coords.y.to_bits().wrapping_mul(10000));
+        state ^= consts.time.wrapping_mul(1337);
         Self { state }
     }
 
     // PCG random num gen. From https://github.com/JMS55/bevy/blob/solari3/crates/bevy_pbr/src/solari/global_illumination/utils.wgsl#L8-L18
     pub fn rand_u(&mut self) -> u32 {
-        self.state = self.state * 747796405 + 2891336453;
-        let word = ((self.state >> ((self.state >> 28) + 4)) ^ self.state) * 277803737;
+        self.state = self.state.wrapping_mul(747796405).wrapping_add(2891336453);
+        let word = ((self.state >> ((self.state >> 28) + 4)) ^ self.state).wrapping_mul(277803737);
         (word >> 22) ^ word
     }
 
@@ -101,6 +155,8 @@ pub struct HitData {
     pub t: f32,
     pub front: bool,
     pub material: Material,
+    // Scene file object that was hit, NO_SELECTION for everything else
+    pub object: u32,
 }
 
 impl HitData {
@@ -110,11 +166,8 @@ impl HitData {
             normal: vec3(0.0, 0.0, 0.0),
             t: 0.0,
             front: false,
-            material: Material {
-                color: Color::new(0.0, 0.0, 0.0),
-                shininess: 0.0,
-                emission: 0.0,
-            },
+            material: Material::default(),
+            object: NO_SELECTION,
         }
     }
 
@@ -126,6 +179,47 @@ impl HitData {
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool;
+
+    // How many intersection tests hit makes, for the hit test view. A shape is
+    // one, ray marching is one per step.
+    fn tests(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> u32 {
+        1
+    }
+}
+
+// Closed shapes that know where the ray enters and leaves them, needed for CSG.
+// Gives the first span along the ray that ends after t_min, normals point outwards.
+pub trait Solid {
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool;
+
+    // How many shapes it's made of
+    fn shapes(&self) -> u32 {
+        1
+    }
+}
+
+// Hit for solids, takes whichever end of the span is the closest one in range
+pub fn hit_solid(
+    solid: &impl Solid,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+    hit_data: &mut HitData,
+) -> bool {
+    let mut enter = HitData::new();
+    let mut exit = HitData::new();
+    if !solid.interval(ray, t_min, &mut enter, &mut exit) {
+        return false;
+    }
+
+    let hit = if enter.t > t_min { enter } else { exit };
+    if hit.t > t_max {
+        return false;
+    }
+
+    *hit_data = hit;
+    hit_data.set_normal(ray, hit.normal);
+    true
 }
 
 #[derive(Copy, Clone)]
@@ -172,6 +266,91 @@ impl Hittable for Sphere {
     }
 }
 
+impl Solid for Sphere {
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool {
+        let oc = ray.origin - self.center;
+        let a = ray.direction.length_squared();
+        let half_b = oc.dot(ray.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+
+        if discriminant < 0.0 {
+            return false;
+        }
+        let sqrt = discriminant.sqrt();
+        let far = (-half_b + sqrt) / a;
+        if far <= t_min {
+            return false;
+        }
+
+        enter.t = (-half_b - sqrt) / a;
+        enter.point = ray.at(enter.t);
+        enter.normal = (enter.point - self.center) / self.radius;
+        enter.material = self.material;
+
+        exit.t = far;
+        exit.point = ray.at(exit.t);
+        exit.normal = (exit.point - self.center) / self.radius;
+        exit.material = self.material;
+
+        true
+    }
+}
+
+// Axis aligned box
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct Cuboid {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub material: Material,
+}
+
+impl Solid for Cuboid {
+    fn interval(&self, ray: &Ray, t_min: f32, enter: &mut HitData, exit: &mut HitData) -> bool {
+        // Slab method, near and far distance to every pair of sides
+        let t1 = (self.min - ray.origin) / ray.direction;
+        let t2 = (self.max - ray.origin) / ray.direction;
+        let near = t1.min(t2);
+        let far = t1.max(t2);
+
+        let t_enter = near.max_element();
+        let t_exit = far.min_element();
+        if t_enter > t_exit || t_exit <= t_min {
+            return false;
+        }
+
+        enter.t = t_enter;
+        enter.point = ray.at(t_enter);
+        enter.normal = -slab_normal(near, t_enter, ray.direction);
+        enter.material = self.material;
+
+        exit.t = t_exit;
+        exit.point = ray.at(t_exit);
+        exit.normal = slab_normal(far, t_exit, ray.direction);
+        exit.material = self.material;
+
+        true
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        hit_solid(self, ray, t_min, t_max, hit_data)
+    }
+}
+
+// Normal of the side that gave the distance t, pointing along the ray
+fn slab_normal(ts: Vec3, t: f32, direction: Vec3) -> Vec3 {
+    if ts.x == t {
+        vec3(direction.x.signum(), 0.0, 0.0)
+    } else if ts.y == t {
+        vec3(0.0, direction.y.signum(), 0.0)
+    } else {
+        vec3(0.0, 0.0, direction.z.signum())
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct Plane {
@@ -218,45 +397,134 @@ impl<T: Hittable, const N: usize> Hittable for [T; N] {
 
         has_hit
     }
+
+    fn tests(&self, ray: &Ray, t_min: f32, t_max: f32) -> u32 {
+        let mut data = HitData::new();
+        let mut closest = t_max;
+        let mut tests = 0;
+
+        for i in 0..N {
+            tests += self[i].tests(ray, t_min, closest);
+            if self[i].hit(ray, t_min, closest, &mut data) {
+                closest = data.t;
+            }
+        }
+
+        tests
+    }
 }
 
+// Same thing for tuples so different kinds of objects can be put in one world.
+// Each side gets its own hit data so an object index from a farther hit can't
+// stick around.
+impl<A: Hittable, B: Hittable> Hittable for (A, B) {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        let mut has_hit = false;
+        let mut closest = t_max;
+
+        let mut data = HitData::new();
+        if self.0.hit(ray, t_min, closest, &mut data) {
+            has_hit = true;
+            closest = data.t;
+            *hit_data = data;
+        }
+        let mut data = HitData::new();
+        if self.1.hit(ray, t_min, closest, &mut data) {
+            has_hit = true;
+            *hit_data = data;
+        }
+
+        has_hit
+    }
+
+    // The second side only gets tested up to where the first one was hit
+    fn tests(&self, ray: &Ray, t_min: f32, t_max: f32) -> u32 {
+        let mut data = HitData::new();
+        let closest = if self.0.hit(ray, t_min, t_max, &mut data) {
+            data.t
+        } else {
+            t_max
+        };
+        self.0.tests(ray, t_min, t_max) + self.1.tests(ray, t_min, closest)
+    }
+}
+
+// One sample's worth of light along a ray, split up into passes
 pub fn ray_color(
     mut ray: Ray,
-    spheres: &impl Hittable,
-    planes: &impl Hittable,
+    world: &impl Hittable,
+    media: &impl Participating,
     rng: &mut RandomSauce,
     max_depth: u32,
     background: Color,
-) -> Color {
+    spectral: bool,
+) -> Aovs {
+    let mut aovs = Aovs::new();
     let mut color = Color::new(1.0, 1.0, 1.0);
-    let mut light = Color::new(0.0, 0.0, 0.0);
+    let mut dielectrics = DielectricStack::new();
+    let mut wavelengths = if spectral {
+        Wavelengths::sample(rng)
+    } else {
+        Wavelengths::rgb()
+    };
+    let background = wavelengths.spectrum(background);
 
-    for _ in 0..max_depth {
-        let mut closest = f32::INFINITY;
-        let mut hit = false;
+    for bounce in 0..max_depth {
         let mut hit_data = HitData::new();
-
-        if spheres.hit(&ray, 0.0001, closest, &mut hit_data) {
-            closest = hit_data.t;
-            hit = true;
-        }
-        if planes.hit(&ray, 0.0001, closest, &mut hit_data) {
-            hit = true;
+        let hit = world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data);
+        let closest = if hit { hit_data.t } else { f32::INFINITY };
+
+        // The ray might bump into something in a medium before reaching the surface
+        let mut event = MediumEvent::new();
+        if media.sample(&ray, 0.0001, closest, rng, &mut event) {
+            if bounce == 0 {
+                aovs.first_volume_hit(&ray, &event);
+            }
+            color *= dielectrics.transmittance(event.t * ray.direction.length());
+            let medium = wavelengths.medium(event.medium);
+            // Nothing comes back out of something that only absorbs
+            if medium.scattering <= 0.0 {
+                break;
+            }
+            ray = Ray::new(event.point, medium.sample_phase(ray.direction, rng));
+            aovs.bounces += 1.0;
+            color *= medium.color * medium.albedo();
+            continue;
         }
 
         if hit {
-            let (r, col) = hit_data.material.scatter(&ray, &hit_data, rng);
-            ray = r;
+            if bounce == 0 {
+                aovs.first_hit(&ray, &hit_data);
+            }
+            hit_data.material = wavelengths.material(hit_data.material);
+            color *= dielectrics.transmittance(hit_data.t * ray.direction.length());
             let emit = hit_data.material.emit();
-            light += color * emit;
-            color *= col;
+            aovs.add_light(bounce, color * emit);
+            aovs.bounces += 1.0;
+
+            let sample = hit_data.material.sample(
+                &ray,
+                &hit_data,
+                world,
+                &mut dielectrics,
+                &mut wavelengths,
+                rng,
+            );
+            if sample.end {
+                break;
+            }
+            ray = sample.ray;
+            color *= sample.weight;
         } else {
-            light += color * background;
+            aovs.add_light(bounce, color * background);
             break;
         }
     }
 
-    light
+    aovs.emission = wavelengths.to_rgb(aovs.emission);
+    aovs.direct = wavelengths.to_rgb(aovs.direct);
+    aovs.indirect = wavelengths.to_rgb(aovs.indirect);
+    aovs
 }
 
 pub fn convert_color(color: f32) -> f32 {
@@ -337,27 +605,86 @@ impl Camera {
     }
 }
 
-#[derive(Copy, Clone)]
+// kind says which bsdf it is, see bsdf.rs for the fields each one uses.
+// Dielectrics tint the light going through them to absorption after
+// absorption_distance, 0 turns that off.
+// Dispersion is the Cauchy B coefficient, only does something in spectral mode.
+// Materials with a mean free path scatter light under their surface instead.
+// Roughness blurs reflections.
+#[derive(Copy, Clone, PartialEq)]
 #[repr(C)]
 pub struct Material {
     pub color: Color,
     pub shininess: f32,
     pub emission: f32,
+    pub ior: f32,
+    pub absorption: Color,
+    pub absorption_distance: f32,
+    pub priority: u32,
+    pub dispersion: f32,
+    pub mean_free_path: Color,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub kind: u32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            emission: 0.0,
+            ior: 0.0,
+            absorption: Color::new(1.0, 1.0, 1.0),
+            absorption_distance: 0.0,
+            priority: 0,
+            dispersion: 0.0,
+            mean_free_path: Color::new(0.0, 0.0, 0.0),
+            roughness: 0.0,
+            metallic: 0.0,
+            kind: BSDF_MIX,
+        }
+    }
 }
 
 impl Material {
-    pub fn scatter(&self, ray: &Ray, hit_data: &HitData, rng: &mut RandomSauce) -> (Ray, Color) {
-        let scatter_dir = if rng.rand_f() < self.shininess {
-            ray.direction.reflect(hit_data.normal)
-        } else {
-            hit_data.normal + rng.rand_unit_vec3()
-        };
+    pub fn ior_at(&self, lambda: f32) -> f32 {
+        cauchy_ior(self.ior, self.dispersion, lambda)
+    }
 
-        let ray = Ray::new(hit_data.point, scatter_dir);
-        let att = self.color;
-        (ray, att)
+    // Reflects or refracts going from ior n1 to n2, also says which one it did
+    pub fn scatter_dielectric(
+        &self,
+        ray: &Ray,
+        hit_data: &HitData,
+        n1: f32,
+        n2: f32,
+        rng: &mut RandomSauce,
+    ) -> (Ray, bool) {
+        let unit = ray.direction.normalize();
+        let cos = (-unit).dot(hit_data.normal).min(1.0);
+        let eta = n1 / n2;
+        let sin2 = eta * eta * (1.0 - cos * cos);
+
+        // Schlick's approximation for how much gets reflected
+        let r0 = ((n1 - n2) / (n1 + n2)) * ((n1 - n2) / (n1 + n2));
+        let reflectance = r0 + (1.0 - r0) * (1.0 - cos).powi(5);
+
+        if sin2 > 1.0 || rng.rand_f() < reflectance {
+            let dir = unit.reflect(hit_data.normal);
+            (Ray::new(hit_data.point, dir), false)
+        } else {
+            let perp = eta * (unit + cos * hit_data.normal);
+            let parallel = -(1.0 - perp.length_squared()).abs().sqrt() * hit_data.normal;
+            (Ray::new(hit_data.point, perp + parallel), true)
+        }
     }
-    pub fn emit(&self) -> Color {
-        self.color * self.emission
+
+    // How much light is left after going distance through the material
+    pub fn transmittance(&self, distance: f32) -> Color {
+        if self.absorption_distance <= 0.0 {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        self.absorption.powf(distance / self.absorption_distance)
     }
 }
@@ -0,0 +1,274 @@
+use crate::{Color, HitData, RandomSauce, Ray, Solid};
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+use spirv_std::num_traits::FloatConst;
+
+// Stuff like fog and smoke with the same density everywhere.
+// Coefficients are per world unit, g is the Henyey-Greenstein asymmetry.
+#[derive(Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct Medium {
+    pub color: Color,
+    pub absorption: f32,
+    pub scattering: f32,
+    pub g: f32,
+}
+
+impl Medium {
+    // Takes all the light without scattering any, for when tracking has to give up
+    pub fn absorbing() -> Self {
+        Self {
+            color: Color::new(0.0, 0.0, 0.0),
+            absorption: 1.0,
+            scattering: 0.0,
+            g: 0.0,
+        }
+    }
+
+    pub fn extinction(&self) -> f32 {
+        self.absorption + self.scattering
+    }
+
+    // Chance that light survives when something happens in the medium
+    pub fn albedo(&self) -> f32 {
+        self.scattering / self.extinction()
+    }
+
+    // How far light gets before hitting a particle, in world units
+    pub fn sample_distance(&self, rng: &mut RandomSauce) -> f32 {
+        -(1.0 - rng.rand_f()).ln() / self.extinction()
+    }
+
+    // Henyey-Greenstein phase function, angle is between the old and new direction
+    pub fn sample_phase(&self, direction: Vec3, rng: &mut RandomSauce) -> Vec3 {
+        let g = self.g;
+        let u = rng.rand_f();
+        let cos_theta = if g.abs() < 0.001 {
+            1.0 - 2.0 * u
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - sq * sq) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = rng.rand_f_range(0.0, 2.0 * f32::PI());
+
+        let forward = direction.normalize();
+        let (t, b) = forward.any_orthonormal_pair();
+        t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + forward * cos_theta
+    }
+}
+
+// Where a ray scattered in a medium
+#[derive(Copy, Clone)]
+pub struct MediumEvent {
+    pub t: f32,
+    pub point: Vec3,
+    pub medium: Medium,
+}
+
+impl MediumEvent {
+    pub fn new() -> Self {
+        Self {
+            t: 0.0,
+            point: vec3(0.0, 0.0, 0.0),
+            medium: Medium {
+                color: Color::new(0.0, 0.0, 0.0),
+                absorption: 0.0,
+                scattering: 0.0,
+                g: 0.0,
+            },
+        }
+    }
+}
+
+pub trait Participating {
+    // Sees if the ray runs into something in the medium between t_min and t_max
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool;
+
+    // Fraction of light that makes it from t_min to t_max without running into anything
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, rng: &mut RandomSauce) -> f32;
+}
+
+// Samples a scatter distance for the part of the ray between start and end
+fn sample_span(
+    medium: &Medium,
+    ray: &Ray,
+    start: f32,
+    end: f32,
+    rng: &mut RandomSauce,
+    event: &mut MediumEvent,
+) -> bool {
+    if start >= end || medium.extinction() <= 0.0 {
+        return false;
+    }
+
+    let t = start + medium.sample_distance(rng) / ray.direction.length();
+    if t >= end {
+        return false;
+    }
+
+    event.t = t;
+    event.point = ray.at(t);
+    event.medium = *medium;
+    true
+}
+
+// Beer-Lambert for the part of the ray between start and end
+fn span_transmittance(medium: &Medium, ray: &Ray, start: f32, end: f32) -> f32 {
+    if start >= end || medium.extinction() <= 0.0 {
+        return 1.0;
+    }
+
+    (-medium.extinction() * (end - start) * ray.direction.length()).exp()
+}
+
+// Medium filling the inside of a solid
+#[derive(Copy, Clone)]
+pub struct Volume<T> {
+    pub boundary: T,
+    pub medium: Medium,
+}
+
+impl<T: Solid> Volume<T> {
+    // Part of the ray inside the boundary, empty if end <= start
+    fn span(&self, ray: &Ray, t_min: f32, t_max: f32) -> (f32, f32) {
+        let mut enter = HitData::new();
+        let mut exit = HitData::new();
+        if !self.boundary.interval(ray, t_min, &mut enter, &mut exit) {
+            return (t_min, t_min);
+        }
+
+        (enter.t.max(t_min), exit.t.min(t_max))
+    }
+}
+
+impl<T: Solid> Participating for Volume<T> {
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool {
+        let (start, end) = self.span(ray, t_min, t_max);
+        sample_span(&self.medium, ray, start, end, rng, event)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, _rng: &mut RandomSauce) -> f32 {
+        let (start, end) = self.span(ray, t_min, t_max);
+        span_transmittance(&self.medium, ray, start, end)
+    }
+}
+
+// Global fog filling everything below height
+#[derive(Copy, Clone, PartialEq)]
+pub struct Fog {
+    pub medium: Medium,
+    pub height: f32,
+}
+
+impl Fog {
+    // Part of the ray below the top of the fog, empty if end <= start
+    fn span(&self, ray: &Ray, t_min: f32, t_max: f32) -> (f32, f32) {
+        if ray.direction.y == 0.0 {
+            if ray.origin.y >= self.height {
+                return (t_min, t_min);
+            }
+            return (t_min, t_max);
+        }
+
+        // Where the ray crosses the top of the fog
+        let cross = (self.height - ray.origin.y) / ray.direction.y;
+        if ray.direction.y > 0.0 {
+            (t_min, t_max.min(cross))
+        } else {
+            (t_min.max(cross), t_max)
+        }
+    }
+}
+
+impl Participating for Fog {
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool {
+        let (start, end) = self.span(ray, t_min, t_max);
+        sample_span(&self.medium, ray, start, end, rng, event)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, _rng: &mut RandomSauce) -> f32 {
+        let (start, end) = self.span(ray, t_min, t_max);
+        span_transmittance(&self.medium, ray, start, end)
+    }
+}
+
+impl<T: Participating, const N: usize> Participating for [T; N] {
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool {
+        let mut has_event = false;
+        let mut closest = t_max;
+
+        for i in 0..N {
+            if self[i].sample(ray, t_min, closest, rng, event) {
+                has_event = true;
+                closest = event.t;
+            }
+        }
+
+        has_event
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, rng: &mut RandomSauce) -> f32 {
+        let mut transmittance = 1.0;
+        for i in 0..N {
+            transmittance *= self[i].transmittance(ray, t_min, t_max, rng);
+        }
+        transmittance
+    }
+}
+
+impl<A: Participating, B: Participating> Participating for (A, B) {
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool {
+        let mut has_event = false;
+        let mut closest = t_max;
+
+        if self.0.sample(ray, t_min, closest, rng, event) {
+            has_event = true;
+            closest = event.t;
+        }
+        if self.1.sample(ray, t_min, closest, rng, event) {
+            has_event = true;
+        }
+
+        has_event
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, rng: &mut RandomSauce) -> f32 {
+        self.0.transmittance(ray, t_min, t_max, rng) * self.1.transmittance(ray, t_min, t_max, rng)
+    }
+}
@@ -0,0 +1,295 @@
+use crate::*;
+use spirv_std::glam::{vec3, vec4, Vec4};
+
+// Everything that happens for one pixel, shared by the shader and the cpu renderer.
+// in_coord is the pixel position like frag_coord, so pixel centers are at .5
+pub fn render_pixel(
+    constants: &ShaderConsts,
+    in_coord: Vec4,
+    voxels: &[f32],
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> Vec4 {
+    let color = trace_pixel(constants, in_coord, voxels, objects, materials).to_srgb();
+    vec4(color.x, color.y, color.z, 1.0)
+}
+
+// Average linear color of all the samples for a pixel
+pub fn trace_pixel(
+    constants: &ShaderConsts,
+    in_coord: Vec4,
+    voxels: &[f32],
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> Color {
+    let aovs = trace_samples(constants, in_coord, voxels, objects, materials);
+    let mut color = aovs.view(constants.view_mode, constants.bounce_limit);
+
+    // Tint whatever is selected in the app
+    if constants.selected != NO_SELECTION && aovs.object == constants.selected {
+        color = color * 0.6 + Color::new(1.0, 0.5, 0.0) * 0.4;
+    }
+
+    color
+}
+
+// Every pass for a pixel, including which material the object from the first
+// sample has
+pub fn pixel_aovs(
+    constants: &ShaderConsts,
+    in_coord: Vec4,
+    voxels: &[f32],
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> Aovs {
+    let mut aovs = trace_samples(constants, in_coord, voxels, objects, materials);
+    if aovs.object != NO_SELECTION {
+        aovs.material = objects[aovs.object as usize].material;
+    }
+    aovs
+}
+
+fn trace_samples(
+    constants: &ShaderConsts,
+    in_coord: Vec4,
+    voxels: &[f32],
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> Aovs {
+    let world = scene_world(constants, objects, materials);
+
+    let smoke = Volume {
+        boundary: Sphere {
+            center: vec3(1.8, 0.8, -3.0),
+            radius: 0.8,
+            material: Material::default(),
+        },
+        medium: Medium {
+            color: Color::new(0.9, 0.9, 0.9),
+            absorption: 0.5,
+            scattering: 3.0,
+            g: 0.3,
+        },
+    };
+
+    let fog = Fog {
+        medium: Medium {
+            color: Color::new(
+                constants.fog_color.0,
+                constants.fog_color.1,
+                constants.fog_color.2,
+            ),
+            absorption: constants.fog_absorption,
+            scattering: constants.fog_scattering,
+            g: constants.fog_g,
+        },
+        height: constants.fog_height,
+    };
+
+    // Voxel grid loaded with --volume, does nothing when there isn't one
+    let cloud = GridVolume {
+        info: constants.grid,
+        density: voxels,
+        medium: Medium {
+            color: Color::new(1.0, 1.0, 1.0),
+            absorption: 0.2,
+            scattering: 4.0,
+            g: 0.5,
+        },
+        scale: constants.grid_density,
+    };
+
+    let media = (smoke, (fog, cloud));
+
+    let camera = camera(constants);
+
+    let integrator = Integrator::new(constants);
+
+    let mut rng = RandomSauce::new(constants, in_coord);
+
+    let mut aovs = Aovs::new();
+    // For the variance
+    let mut brightness = 0.0;
+    let mut brightness_squared = 0.0;
+
+    let pdu = camera.pdu();
+    let pdv = camera.pdv();
+    let first = camera.first();
+
+    for i in 0..camera.samples {
+        let offset_x = rng.rand_f() - 0.5;
+        let offset_y = rng.rand_f() - 0.5;
+
+        let pixel_center =
+            first + pdu * (in_coord.x + offset_x) + pdv * (camera.height - (in_coord.y + offset_y));
+        let ray_direction = pixel_center - camera.pos;
+        let ray = Ray::new(camera.pos, ray_direction);
+
+        let sample = integrator.radiance(ray, &world.0, &world, &media, &mut rng);
+        let light = sample.light();
+        let invalid = is_invalid(light);
+        if !invalid {
+            brightness += light.average();
+            brightness_squared += light.average() * light.average();
+        }
+
+        if i == 0 {
+            aovs = sample;
+            // Marching the sdfs again is slow, so only when it gets shown
+            if constants.view_mode == VIEW_TESTS {
+                aovs.tests = world.tests(&ray, 0.0001, f32::INFINITY) as f32;
+            }
+        } else {
+            aovs.add_sample(&sample);
+        }
+        if invalid {
+            aovs.invalid += 1;
+        }
+    }
+
+    let samples = camera.samples as f32;
+    aovs.divide_samples(samples);
+    let mean = brightness / samples;
+    aovs.variance = (brightness_squared / samples - mean * mean).max(0.0);
+    aovs
+}
+
+// Which scene object is seen through the middle of a pixel, NO_SELECTION if it's
+// none of them. For picking in the app.
+pub fn object_at(
+    constants: &ShaderConsts,
+    in_coord: Vec4,
+    objects: &[SceneObject],
+    materials: &[MaterialData],
+) -> u32 {
+    let world = scene_world(constants, objects, materials);
+    let camera = camera(constants);
+    let pixel =
+        camera.first() + camera.pdu() * in_coord.x + camera.pdv() * (camera.height - in_coord.y);
+    let ray = Ray::new(camera.pos, pixel - camera.pos);
+
+    // Stays NO_SELECTION when nothing or something not from the scene file is hit
+    let mut hit_data = HitData::new();
+    world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data);
+    hit_data.object
+}
+
+fn camera(constants: &ShaderConsts) -> Camera {
+    Camera::new(
+        constants.width,
+        constants.height,
+        constants.samples,
+        constants.fov,
+        vec3(constants.pos.0, constants.pos.1, constants.pos.2),
+        constants.yaw,
+        constants.pitch,
+    )
+}
+
+// Objects from the scene file first, then everything else
+fn scene_world<'a>(
+    constants: &ShaderConsts,
+    objects: &'a [SceneObject],
+    materials: &'a [MaterialData],
+) -> (ObjectList<'a>, impl Hittable) {
+    // The scene file only has simple shapes, the fancier ones are still made here.
+    // The outliner lists them in its BUILT_IN, keep that in step.
+    let greenmat = Material {
+        color: Color::new(0.0, 1.0, 0.0),
+        shininess: 0.0,
+        emission: 0.0,
+        ..Default::default()
+    };
+    let yellowmat = Material {
+        color: Color::new(1.0, 1.0, 0.0),
+        shininess: 0.8,
+        emission: 0.0,
+        ..Default::default()
+    };
+
+    let glassmat = Material {
+        color: Color::new(1.0, 1.0, 1.0),
+        ior: 1.5,
+        absorption: Color::new(0.8, 0.95, 0.9),
+        absorption_distance: 1.0,
+        priority: 3,
+        dispersion: 0.02,
+        kind: BSDF_DIELECTRIC,
+        ..Default::default()
+    };
+
+    // Lens from two overlapping spheres
+    let lens = Intersection {
+        a: Sphere {
+            center: vec3(0.9, 0.45, 0.9),
+            radius: 0.6,
+            material: glassmat,
+        },
+        b: Sphere {
+            center: vec3(0.9, 0.45, -0.1),
+            radius: 0.6,
+            material: glassmat,
+        },
+    };
+
+    // Hollow sphere with a corner cut off so you can see inside
+    let hollow = Difference {
+        a: Difference {
+            a: Sphere {
+                center: vec3(-1.1, 0.45, 0.3),
+                radius: 0.45,
+                material: greenmat,
+            },
+            b: Sphere {
+                center: vec3(-1.1, 0.45, 0.3),
+                radius: 0.4,
+                material: yellowmat,
+            },
+        },
+        b: Cuboid {
+            min: vec3(-1.1, 0.45, 0.3),
+            max: vec3(-0.5, 1.0, 0.9),
+            material: yellowmat,
+        },
+    };
+
+    let mandelbulb = RayMarched {
+        shape: Mandelbulb {
+            center: vec3(-0.4, 1.4, -3.2),
+            scale: 0.6,
+            power: 8.0,
+            iterations: 8,
+        },
+        material: yellowmat,
+        max_steps: constants.march_steps,
+        epsilon: constants.march_epsilon,
+    };
+
+    // Rounded box with a ball melting into the top of it
+    let blob = RayMarched {
+        shape: SmoothUnion {
+            a: RoundBox {
+                center: vec3(2.2, 0.25, 0.4),
+                half_size: vec3(0.25, 0.25, 0.25),
+                radius: 0.05,
+            },
+            b: SdfSphere {
+                center: vec3(2.2, 0.6, 0.4),
+                radius: 0.2,
+            },
+            k: 0.15,
+        },
+        material: greenmat,
+        max_steps: constants.march_steps,
+        epsilon: constants.march_epsilon,
+    };
+
+    // Everything from the scene file
+    let scene = ObjectList {
+        objects,
+        materials,
+        count: constants.object_count,
+    };
+
+    (scene, ((lens, hollow), (mandelbulb, blob)))
+}
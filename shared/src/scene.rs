@@ -0,0 +1,206 @@
+use crate::*;
+use spirv_std::glam::{vec3, vec4, Vec3, Vec4};
+use spirv_std::num_traits::Float;
+
+pub const SHAPE_SPHERE: u32 = 0;
+pub const SHAPE_CUBOID: u32 = 1;
+pub const SHAPE_PLANE: u32 = 2;
+
+// ShaderConsts::selected when nothing is selected
+pub const NO_SELECTION: u32 = u32::MAX;
+
+// Object from the scene file the way it is in the gpu buffer. Only vec4s and
+// four u32s so the layout is the same on both sides.
+// Sphere: a is the center and radius. Cuboid: a is min and the turn around the
+// vertical axis through its center in radians, b is max. Plane: a.y is the height.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct SceneObject {
+    pub a: Vec4,
+    pub b: Vec4,
+    pub shape: u32,
+    pub material: u32,
+    pub _pad0: u32,
+    pub _pad1: u32,
+}
+
+impl SceneObject {
+    pub fn sphere(center: Vec3, radius: f32, material: u32) -> Self {
+        Self::new(SHAPE_SPHERE, center.extend(radius), Vec4::ZERO, material)
+    }
+
+    pub fn cuboid(min: Vec3, max: Vec3, rotation: f32, material: u32) -> Self {
+        Self::new(
+            SHAPE_CUBOID,
+            min.extend(rotation),
+            max.extend(0.0),
+            material,
+        )
+    }
+
+    pub fn plane(y: f32, material: u32) -> Self {
+        Self::new(SHAPE_PLANE, vec4(0.0, y, 0.0, 0.0), Vec4::ZERO, material)
+    }
+
+    fn new(shape: u32, a: Vec4, b: Vec4, material: u32) -> Self {
+        Self {
+            a,
+            b,
+            shape,
+            material,
+            _pad0: 0,
+            _pad1: 0,
+        }
+    }
+
+    // Material gets filled in after the closest hit is known
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        let material = Material::default();
+        match self.shape {
+            SHAPE_SPHERE => Sphere {
+                center: self.a.truncate(),
+                radius: self.a.w,
+                material,
+            }
+            .hit(ray, t_min, t_max, hit_data),
+            SHAPE_CUBOID => {
+                let cuboid = Cuboid {
+                    min: self.a.truncate(),
+                    max: self.b.truncate(),
+                    material,
+                };
+                // The ray gets turned into the box's frame and the hit turned back
+                let center = (cuboid.min + cuboid.max) * 0.5;
+                let (sin, cos) = self.a.w.sin_cos();
+                let local = Ray::new(
+                    turn_y(ray.origin - center, -sin, cos) + center,
+                    turn_y(ray.direction, -sin, cos),
+                );
+                if !cuboid.hit(&local, t_min, t_max, hit_data) {
+                    return false;
+                }
+                hit_data.point = turn_y(hit_data.point - center, sin, cos) + center;
+                hit_data.normal = turn_y(hit_data.normal, sin, cos);
+                true
+            }
+            _ => Plane {
+                y: self.a.y,
+                material,
+            }
+            .hit(ray, t_min, t_max, hit_data),
+        }
+    }
+}
+
+// Turns v around the y axis by the angle with that sine and cosine
+fn turn_y(v: Vec3, sin: f32, cos: f32) -> Vec3 {
+    vec3(cos * v.x + sin * v.z, v.y, cos * v.z - sin * v.x)
+}
+
+// Material packed into vec4s for the gpu buffer
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MaterialData {
+    // rgb and shininess
+    pub color: Vec4,
+    // rgb and absorption distance
+    pub absorption: Vec4,
+    // rgb and emission
+    pub mean_free_path: Vec4,
+    // ior, dispersion, priority and kind
+    pub optics: Vec4,
+    // roughness and metallic
+    pub surface: Vec4,
+}
+
+impl MaterialData {
+    pub fn new(material: &Material) -> Self {
+        let c = |color: Color, w: f32| vec4(color.r(), color.g(), color.b(), w);
+        Self {
+            color: c(material.color, material.shininess),
+            absorption: c(material.absorption, material.absorption_distance),
+            mean_free_path: c(material.mean_free_path, material.emission),
+            optics: vec4(
+                material.ior,
+                material.dispersion,
+                material.priority as f32,
+                material.kind as f32,
+            ),
+            surface: vec4(material.roughness, material.metallic, 0.0, 0.0),
+        }
+    }
+
+    pub fn material(&self) -> Material {
+        let c = |v: Vec4| Color::new(v.x, v.y, v.z);
+        Material {
+            color: c(self.color),
+            shininess: self.color.w,
+            emission: self.mean_free_path.w,
+            ior: self.optics.x,
+            absorption: c(self.absorption),
+            absorption_distance: self.absorption.w,
+            priority: self.optics.z as u32,
+            dispersion: self.optics.y,
+            mean_free_path: c(self.mean_free_path),
+            roughness: self.surface.x,
+            metallic: self.surface.y,
+            kind: self.optics.w as u32,
+        }
+    }
+}
+
+// All the objects from the scene buffers, count comes from the shader constants
+// because the buffers always have at least one thing in them
+pub struct ObjectList<'a> {
+    pub objects: &'a [SceneObject],
+    pub materials: &'a [MaterialData],
+    pub count: u32,
+}
+
+// Hits also say which object it was in HitData::object
+impl Hittable for ObjectList<'_> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        let mut has_hit = false;
+        let mut closest = t_max;
+        let mut index = 0;
+
+        for i in 0..self.count {
+            let object = self.objects[i as usize];
+            if object.hit(ray, t_min, closest, hit_data) {
+                has_hit = true;
+                closest = hit_data.t;
+                index = i;
+            }
+        }
+
+        if has_hit {
+            let material = self.objects[index as usize].material;
+            hit_data.material = self.materials[material as usize].material();
+            hit_data.object = index;
+        }
+        has_hit
+    }
+
+    fn tests(&self, _ray: &Ray, _t_min: f32, _t_max: f32) -> u32 {
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turned_cuboid_is_hit_on_its_corner() {
+        let rotation = core::f32::consts::FRAC_PI_4;
+        let cuboid = SceneObject::cuboid(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5), rotation, 0);
+        let ray = Ray::new(vec3(-5.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let mut hit_data = HitData::new();
+
+        assert!(cuboid.hit(&ray, 0.001, f32::INFINITY, &mut hit_data));
+        // Turned by 45 degrees the corner sticks out to half the diagonal
+        assert!((hit_data.t - (5.0 - 0.5f32.sqrt())).abs() < 1e-4);
+        assert!(hit_data.point.z.abs() < 1e-4);
+        assert!(hit_data.normal.x < 0.0 && (hit_data.normal.length() - 1.0).abs() < 1e-4);
+    }
+}
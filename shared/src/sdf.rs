@@ -0,0 +1,231 @@
+use crate::{HitData, Hittable, Material, Ray};
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+
+// Marching stops when it gets this far away without hitting anything
+const MAX_MARCH_DISTANCE: f32 = 100.0;
+
+pub trait Sdf {
+    fn distance(&self, p: Vec3) -> f32;
+}
+
+#[derive(Copy, Clone)]
+pub struct SdfSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: Vec3) -> f32 {
+        (p - self.center).length() - self.radius
+    }
+}
+
+// Box with the corners rounded off by radius, half_size includes the rounding
+#[derive(Copy, Clone)]
+pub struct RoundBox {
+    pub center: Vec3,
+    pub half_size: Vec3,
+    pub radius: f32,
+}
+
+impl Sdf for RoundBox {
+    fn distance(&self, p: Vec3) -> f32 {
+        let q = (p - self.center).abs() - self.half_size + Vec3::splat(self.radius);
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0) - self.radius
+    }
+}
+
+// Blends two shapes together, k is how far the blending reaches
+#[derive(Copy, Clone)]
+pub struct SmoothUnion<A, B> {
+    pub a: A,
+    pub b: B,
+    pub k: f32,
+}
+
+impl<A: Sdf, B: Sdf> Sdf for SmoothUnion<A, B> {
+    fn distance(&self, p: Vec3) -> f32 {
+        // Polynomial smooth min from https://iquilezles.org/articles/smin/
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Mandelbulb {
+    pub center: Vec3,
+    pub scale: f32,
+    pub power: f32,
+    pub iterations: u32,
+}
+
+impl Sdf for Mandelbulb {
+    fn distance(&self, p: Vec3) -> f32 {
+        // Distance estimator from http://blog.hvidtfeldts.net/index.php/2011/09/distance-estimated-3d-fractals-v-the-mandelbulb-different-de-approximations/
+        let c = (p - self.center) / self.scale;
+        let mut z = c;
+        let mut dr = 1.0;
+        let mut r = z.length();
+
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+
+            let theta = (z.z / r.max(0.00001)).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            let zr = r.powf(self.power);
+            z =
+                zr * vec3(
+                    theta.sin() * phi.cos(),
+                    phi.sin() * theta.sin(),
+                    theta.cos(),
+                ) + c;
+            r = z.length();
+        }
+
+        // r ln r goes to 0 at the center, but ln(0) times 0 is NaN
+        if r <= 0.0 {
+            return 0.0;
+        }
+        0.5 * r.ln() * r / dr * self.scale
+    }
+}
+
+// Puts an sdf in the world by sphere tracing it
+#[derive(Copy, Clone)]
+pub struct RayMarched<T> {
+    pub shape: T,
+    pub material: Material,
+    pub max_steps: u32,
+    pub epsilon: f32,
+}
+
+impl<T: Sdf> RayMarched<T> {
+    // Gradient of the distance field with central differences
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let e = self.epsilon;
+        vec3(
+            self.shape.distance(p + vec3(e, 0.0, 0.0)) - self.shape.distance(p - vec3(e, 0.0, 0.0)),
+            self.shape.distance(p + vec3(0.0, e, 0.0)) - self.shape.distance(p - vec3(0.0, e, 0.0)),
+            self.shape.distance(p + vec3(0.0, 0.0, e)) - self.shape.distance(p - vec3(0.0, 0.0, e)),
+        )
+        .normalize()
+    }
+
+    // Whether it hit, where and how many steps it took. The distance is along
+    // the ray in the same units as t.
+    fn march(&self, ray: &Ray, t_min: f32, t_max: f32) -> (bool, f32, u32) {
+        // March in world units since the ray direction isn't normalized
+        let length = ray.direction.length();
+        let dir = ray.direction / length;
+        let max_dist = (t_max * length).min(MAX_MARCH_DISTANCE);
+        let mut dist = t_min * length;
+
+        // Rays bouncing off or going through the surface start on it, so ignore hits
+        // until they got away. After that the ray is on one side, inside when the
+        // distance is negative, and the surface is wherever that changes.
+        let start = self.shape.distance(ray.origin + dir * dist);
+        let mut escaped = start.abs() > self.epsilon;
+        let mut inside = start < 0.0;
+
+        let mut steps = 0;
+        while steps < self.max_steps {
+            steps += 1;
+            let d = self.shape.distance(ray.origin + dir * dist);
+            if !escaped && d.abs() > self.epsilon {
+                escaped = true;
+                inside = d < 0.0;
+            }
+
+            if !escaped {
+                dist += self.epsilon;
+            } else if d.abs() < self.epsilon || (d < 0.0) != inside {
+                return (true, dist / length, steps);
+            } else {
+                dist += d.abs();
+            }
+
+            if dist > max_dist {
+                break;
+            }
+        }
+
+        (false, 0.0, steps)
+    }
+}
+
+impl<T: Sdf> Hittable for RayMarched<T> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32, hit_data: &mut HitData) -> bool {
+        let (hit, t, _) = self.march(ray, t_min, t_max);
+        if !hit {
+            return false;
+        }
+        hit_data.t = t;
+        hit_data.point = ray.at(t);
+        let out_normal = self.normal(hit_data.point);
+        hit_data.set_normal(ray, out_normal);
+        hit_data.material = self.material;
+        true
+    }
+
+    fn tests(&self, ray: &Ray, t_min: f32, t_max: f32) -> u32 {
+        self.march(ray, t_min, t_max).2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marched<T: Sdf>(shape: T) -> RayMarched<T> {
+        RayMarched {
+            shape,
+            material: HitData::new().material,
+            max_steps: 128,
+            epsilon: 0.0001,
+        }
+    }
+
+    #[test]
+    fn finds_the_way_out() {
+        let sphere = marched(SdfSphere {
+            center: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let ray = Ray::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let mut hit_data = HitData::new();
+        assert!(sphere.hit(&ray, 0.0, f32::INFINITY, &mut hit_data));
+        assert!((hit_data.t - 1.0).abs() < 0.001);
+        assert!(!hit_data.front);
+    }
+
+    #[test]
+    fn mandelbulb_center_is_a_number() {
+        let bulb = Mandelbulb {
+            center: vec3(1.0, 2.0, 3.0),
+            scale: 1.0,
+            power: 8.0,
+            iterations: 8,
+        };
+        assert!(!bulb.distance(bulb.center).is_nan());
+    }
+
+    #[test]
+    fn counts_march_steps() {
+        let sphere = marched(SdfSphere {
+            center: vec3(5.0, 0.0, 0.0),
+            radius: 1.0,
+        });
+        let ray = Ray::new(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        // One step gets to the surface and the next finds it
+        assert_eq!(sphere.tests(&ray, 0.0, f32::INFINITY), 2);
+        // The first step already goes past a closer t_max
+        assert_eq!(sphere.tests(&ray, 0.0, 2.0), 1);
+    }
+}
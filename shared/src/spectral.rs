@@ -0,0 +1,151 @@
+use crate::{Color, Material, Medium, RandomSauce};
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+
+const LAMBDA_MIN: f32 = 380.0;
+const LAMBDA_MAX: f32 = 720.0;
+
+// Wavelength the plain ior of a material is for, the sodium D line
+const LAMBDA_IOR: f32 = 589.3;
+
+// Integral of the y matching function between LAMBDA_MIN and LAMBDA_MAX
+const CIE_Y_INTEGRAL: f32 = 106.912;
+
+// What a constant spectrum of 1 turns into, dividing by this keeps white white
+const WHITE_BALANCE: Vec3 = Vec3::new(1.2006, 0.9497, 0.9078);
+
+// Spectral mode puts one wavelength in each channel of a Color instead of rgb, so
+// everything in the bounce loop works the same. When it's off everything passes through.
+#[derive(Copy, Clone)]
+pub struct Wavelengths {
+    pub lambda: Vec3,
+    pub enabled: bool,
+    // Set once only the first wavelength is left
+    pub collapsed: bool,
+}
+
+impl Wavelengths {
+    pub fn rgb() -> Self {
+        Self {
+            lambda: Vec3::splat(LAMBDA_IOR),
+            enabled: false,
+            collapsed: false,
+        }
+    }
+
+    // Random first wavelength, the others are spread evenly after it
+    pub fn sample(rng: &mut RandomSauce) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = rng.rand_f() * range;
+        let offsets = vec3(0.0, range / 3.0, 2.0 * range / 3.0);
+        Self {
+            lambda: Vec3::splat(LAMBDA_MIN) + (Vec3::splat(hero) + offsets) % range,
+            enabled: true,
+            collapsed: false,
+        }
+    }
+
+    // The wavelength that survives when the path can only follow one of them
+    pub fn hero(&self) -> f32 {
+        self.lambda.x
+    }
+
+    // Turns an rgb value into the spectrum at our wavelengths. Cheap smooth blend of
+    // three bands that add up to 1, so white stays flat. Doesn't round trip exactly.
+    pub fn spectrum(&self, color: Color) -> Color {
+        if !self.enabled {
+            return color;
+        }
+        Color::new(
+            upsample(color, self.lambda.x),
+            upsample(color, self.lambda.y),
+            upsample(color, self.lambda.z),
+        )
+    }
+
+    pub fn material(&self, material: Material) -> Material {
+        if !self.enabled {
+            return material;
+        }
+        Material {
+            color: self.spectrum(material.color),
+            absorption: self.spectrum(material.absorption),
+            mean_free_path: self.spectrum(material.mean_free_path),
+            ..material
+        }
+    }
+
+    pub fn medium(&self, medium: Medium) -> Medium {
+        Medium {
+            color: self.spectrum(medium.color),
+            ..medium
+        }
+    }
+
+    // Dispersion sends every wavelength its own way, so only the hero keeps going.
+    // It gets the weight of all three to stay unbiased.
+    pub fn collapse(&mut self) -> Color {
+        if !self.enabled || self.collapsed {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        self.collapsed = true;
+        Color::new(3.0, 0.0, 0.0)
+    }
+
+    // Radiance at our wavelengths back to linear rgb through the CIE matching functions
+    pub fn to_rgb(&self, radiance: Color) -> Color {
+        if !self.enabled {
+            return radiance;
+        }
+
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let xyz = (cie_xyz(self.lambda.x) * radiance.r()
+            + cie_xyz(self.lambda.y) * radiance.g()
+            + cie_xyz(self.lambda.z) * radiance.b())
+            / (3.0 * pdf * CIE_Y_INTEGRAL);
+
+        let rgb = vec3(
+            3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+            -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+            0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+        ) / WHITE_BALANCE;
+        Color::new(rgb.x, rgb.y, rgb.z)
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn upsample(color: Color, lambda: f32) -> f32 {
+    let green_start = smoothstep(460.0, 520.0, lambda);
+    let red_start = smoothstep(560.0, 620.0, lambda);
+    color.b() * (1.0 - green_start) + color.g() * (green_start - red_start) + color.r() * red_start
+}
+
+// Piecewise gaussian
+fn g(lambda: f32, mu: f32, sigma1: f32, sigma2: f32) -> f32 {
+    let sigma = if lambda < mu { sigma1 } else { sigma2 };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 matching functions, multi lobe fit from
+// https://jcgt.org/published/0002/02/01/ (Wyman, Sloan, Shirley)
+fn cie_xyz(lambda: f32) -> Vec3 {
+    vec3(
+        1.056 * g(lambda, 599.8, 37.9, 31.0) + 0.362 * g(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * g(lambda, 501.1, 20.4, 26.2),
+        0.821 * g(lambda, 568.8, 46.9, 40.5) + 0.286 * g(lambda, 530.9, 16.3, 31.1),
+        1.217 * g(lambda, 437.0, 11.8, 36.0) + 0.681 * g(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// Cauchy's equation, shifted so the material's ior is the one at LAMBDA_IOR.
+// dispersion is the B coefficient in square micrometers.
+pub fn cauchy_ior(ior: f32, dispersion: f32, lambda: f32) -> f32 {
+    let um = lambda / 1000.0;
+    let um_ior = LAMBDA_IOR / 1000.0;
+    ior + dispersion * (1.0 / (um * um) - 1.0 / (um_ior * um_ior))
+}
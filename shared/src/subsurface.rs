@@ -0,0 +1,59 @@
+use crate::{Color, HitData, Hittable, RandomSauce, Ray};
+use spirv_std::num_traits::Float;
+
+// Walks that take longer than this are thrown away
+const MAX_WALK_STEPS: u32 = 64;
+
+// Random walk subsurface scattering. Enters the shape where entry hit it, bounces
+// around inside until it gets back out through the surface, so the shape has to be
+// closed. Every channel has its own mean free path, the distances are sampled with
+// a random channel and weighted by the average pdf over all of them.
+// Returns the ray leaving the surface and its throughput, or false if the walk got lost.
+pub fn random_walk(
+    entry: &HitData,
+    world: &impl Hittable,
+    rng: &mut RandomSauce,
+) -> (Ray, Color, bool) {
+    let material = entry.material;
+    let mfp = material.mean_free_path;
+    let sigma_t = Color::new(
+        1.0 / mfp.r().max(0.0001),
+        1.0 / mfp.g().max(0.0001),
+        1.0 / mfp.b().max(0.0001),
+    );
+    let albedo = material.color;
+
+    // Go in through the surface diffusely, the normal points out of the shape here
+    let mut ray = Ray::new(entry.point, -entry.normal + rng.rand_unit_vec3());
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+
+    for _ in 0..MAX_WALK_STEPS {
+        let mut hit_data = HitData::new();
+        if !world.hit(&ray, 0.0001, f32::INFINITY, &mut hit_data) {
+            break;
+        }
+
+        let length = ray.direction.length();
+        let surface_dist = hit_data.t * length;
+
+        let channel = ((rng.rand_f() * 3.0) as u32).min(2);
+        let dist = -(1.0 - rng.rand_f()).ln() / sigma_t.channel(channel);
+
+        if dist < surface_dist {
+            // Scattered inside, carry on in a random direction
+            let transmittance = (sigma_t * -dist).exp();
+            let pdf = (sigma_t * transmittance).average();
+            throughput *= albedo * sigma_t * transmittance / pdf;
+            ray = Ray::new(ray.at(dist / length), rng.rand_unit_vec3());
+        } else {
+            // Made it to the surface, the normal faces inwards now so leave the other way
+            let transmittance = (sigma_t * -surface_dist).exp();
+            let pdf = transmittance.average();
+            throughput *= transmittance / pdf;
+            let exit = Ray::new(hit_data.point, -hit_data.normal + rng.rand_unit_vec3());
+            return (exit, throughput, true);
+        }
+    }
+
+    (ray, throughput, false)
+}
@@ -1,7 +1,7 @@
 use spirv_std::glam::{vec2, vec3, vec4, Vec2, Vec3, Vec4};
 use spirv_std::num_traits::Float;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 #[repr(transparent)]
 pub struct Color(Vec3);
 
@@ -22,6 +22,26 @@ impl Color {
         self.0.z
     }
 
+    pub fn channel(&self, i: u32) -> f32 {
+        match i {
+            0 => self.0.x,
+            1 => self.0.y,
+            _ => self.0.z,
+        }
+    }
+
+    pub fn average(&self) -> f32 {
+        (self.0.x + self.0.y + self.0.z) / 3.0
+    }
+
+    pub fn exp(&self) -> Self {
+        Self::new(self.0.x.exp(), self.0.y.exp(), self.0.z.exp())
+    }
+
+    pub fn powf(&self, n: f32) -> Self {
+        Self::new(self.0.x.powf(n), self.0.y.powf(n), self.0.z.powf(n))
+    }
+
     pub fn to_srgb(&self) -> Vec3 {
         vec3(
             self.0.x.powf(1.0 / 2.2),
@@ -0,0 +1,159 @@
+use crate::{Medium, MediumEvent, Participating, RandomSauce, Ray};
+use spirv_std::glam::{vec3, Vec3};
+use spirv_std::num_traits::Float;
+
+// Tracking gives up after this many steps through the grid, the light counts as
+// absorbed then since letting it through would make dense grids too bright
+const MAX_TRACKING_STEPS: u32 = 256;
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Where a voxel grid sits in the world, the densities themselves live in a buffer
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct GridInfo {
+    pub dims: (u32, u32, u32),
+    pub min: (f32, f32, f32),
+    pub max: (f32, f32, f32),
+    pub max_density: f32,
+}
+
+impl GridInfo {
+    pub fn empty() -> Self {
+        Self {
+            dims: (0, 0, 0),
+            min: (0.0, 0.0, 0.0),
+            max: (0.0, 0.0, 0.0),
+            max_density: 0.0,
+        }
+    }
+
+    pub fn min(&self) -> Vec3 {
+        vec3(self.min.0, self.min.1, self.min.2)
+    }
+
+    pub fn max(&self) -> Vec3 {
+        vec3(self.max.0, self.max.1, self.max.2)
+    }
+}
+
+// Medium where the density comes from a dense voxel grid, x changes fastest in the data.
+// The medium coefficients are for density 1 and get multiplied by the voxel values.
+pub struct GridVolume<'a> {
+    pub info: GridInfo,
+    pub density: &'a [f32],
+    pub medium: Medium,
+    pub scale: f32,
+}
+
+impl<'a> GridVolume<'a> {
+    fn voxel(&self, x: u32, y: u32, z: u32) -> f32 {
+        let (nx, ny, _) = self.info.dims;
+        self.density[(x + nx * (y + ny * z)) as usize]
+    }
+
+    // Trilinear lookup, voxel centers sit in the middle of their cells
+    pub fn density_at(&self, p: Vec3) -> f32 {
+        let (nx, ny, nz) = self.info.dims;
+        let size = self.info.max() - self.info.min();
+        let local =
+            (p - self.info.min()) / size * vec3(nx as f32, ny as f32, nz as f32) - Vec3::splat(0.5);
+        let top = vec3(nx as f32 - 1.0, ny as f32 - 1.0, nz as f32 - 1.0);
+        let local = local.max(Vec3::ZERO).min(top);
+
+        let x0 = local.x as u32;
+        let y0 = local.y as u32;
+        let z0 = local.z as u32;
+        let x1 = (x0 + 1).min(nx - 1);
+        let y1 = (y0 + 1).min(ny - 1);
+        let z1 = (z0 + 1).min(nz - 1);
+        let f = local - vec3(x0 as f32, y0 as f32, z0 as f32);
+
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), f.x);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), f.x);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), f.x);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), f.x);
+        lerp(lerp(c00, c10, f.y), lerp(c01, c11, f.y), f.z) * self.scale
+    }
+
+    // Part of the ray inside the grid bounds, empty if end <= start
+    fn span(&self, ray: &Ray, t_min: f32, t_max: f32) -> (f32, f32) {
+        let t1 = (self.info.min() - ray.origin) / ray.direction;
+        let t2 = (self.info.max() - ray.origin) / ray.direction;
+        let start = t1.min(t2).max_element().max(t_min);
+        let end = t1.max(t2).min_element().min(t_max);
+        (start, end)
+    }
+
+    // Extinction can never go above this anywhere in the grid
+    fn majorant(&self) -> f32 {
+        self.info.max_density * self.scale * self.medium.extinction()
+    }
+}
+
+impl<'a> Participating for GridVolume<'a> {
+    // Delta tracking, takes steps as if the whole grid was at max density
+    // and throws away the collisions that wouldn't have happened
+    fn sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        rng: &mut RandomSauce,
+        event: &mut MediumEvent,
+    ) -> bool {
+        let majorant = self.majorant();
+        let (start, end) = self.span(ray, t_min, t_max);
+        if self.info.dims.0 == 0 || majorant <= 0.0 || start >= end {
+            return false;
+        }
+
+        let length = ray.direction.length();
+        let mut t = start;
+        for _ in 0..MAX_TRACKING_STEPS {
+            t += -(1.0 - rng.rand_f()).ln() / majorant / length;
+            if t >= end {
+                return false;
+            }
+
+            let point = ray.at(t);
+            let extinction = self.density_at(point) * self.medium.extinction();
+            if rng.rand_f() < extinction / majorant {
+                event.t = t;
+                event.point = point;
+                event.medium = self.medium;
+                return true;
+            }
+        }
+
+        event.t = t;
+        event.point = ray.at(t);
+        event.medium = Medium::absorbing();
+        true
+    }
+
+    // Ratio tracking, same steps but every one of them just dims the light a bit
+    fn transmittance(&self, ray: &Ray, t_min: f32, t_max: f32, rng: &mut RandomSauce) -> f32 {
+        let majorant = self.majorant();
+        let (start, end) = self.span(ray, t_min, t_max);
+        if self.info.dims.0 == 0 || majorant <= 0.0 || start >= end {
+            return 1.0;
+        }
+
+        let length = ray.direction.length();
+        let mut transmittance = 1.0;
+        let mut t = start;
+        for _ in 0..MAX_TRACKING_STEPS {
+            t += -(1.0 - rng.rand_f()).ln() / majorant / length;
+            if t >= end {
+                return transmittance;
+            }
+            let extinction = self.density_at(ray.at(t)) * self.medium.extinction();
+            transmittance *= 1.0 - extinction / majorant;
+        }
+
+        0.0
+    }
+}